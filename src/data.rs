@@ -1,4 +1,5 @@
 use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Formatter};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -9,8 +10,11 @@ use bevy_ecs::component::Component;
 use bevy_ecs::entity::Entity;
 use bevy_ecs::event::EventWriter;
 use bevy_ecs::system::SystemParam;
+use bevy_math::{Vec2, Vec3};
 use bevy_render::camera::OrthographicProjection;
 use bevy_render::texture::{BevyDefault, Image};
+use bevy_render::view::RenderLayers;
+use bevy_transform::components::Transform;
 use wgpu::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
 
 pub type RecorderID = usize;
@@ -22,6 +26,73 @@ pub struct Recorder(pub RecorderID);
 /// should track the referenced entity
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Component)]
 pub struct Track(pub Entity);
+/// Add to a recorder's tracking camera to crop every frame it stores to a box of `width` by
+/// `height` pixels, centred on the position of `target`. The box is kept inside the frame, and
+/// is centred on the frame while the target doesn't exist. The tracking camera for a recorder
+/// is available as `ActiveRecorder::tracker`
+///
+/// A [`CaptureRegion::Pixels`] crop on a capture request is measured from the top left corner
+/// of the box. The box moves with the target, so [`CaptureRegion::World`] crops can't be used
+/// with it; they are ignored with a warning, and the whole box is captured
+///
+/// ## Example
+///
+/// ```ignore
+/// pub fn follow_player(
+/// 	mut commands: Commands,
+/// 	recorders: Res<ActiveRecorders>,
+/// 	player: Query<Entity, Added<Player>>,
+/// ) {
+/// 	for player in &player {
+/// 		if let Some(recorder) = recorders.get(&1) {
+/// 			commands.entity(recorder.tracker).insert(Follow {
+/// 				target: player,
+/// 				width: 128,
+/// 				height: 128,
+/// 			});
+/// 		}
+/// 	}
+/// }
+/// ```
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Component)]
+pub struct Follow {
+	pub target: Entity,
+	pub width: u32,
+	pub height: u32,
+}
+/// Hide an entity in every capture, such as a player's name tag. A box of `size` world units,
+/// centred on the entity and following its transform, is covered up in every frame stored while
+/// the entity is visible to a recorder
+#[derive(Clone, Copy, PartialEq, Debug, Component)]
+pub struct RedactInCapture {
+	/// The size of the box to cover, before the entity's scale and rotation are applied
+	pub size: Vec2,
+	pub style: RedactionStyle,
+}
+
+/// How a redacted part of a frame is covered up
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RedactionStyle {
+	/// Fill the area with a solid RGBA colour
+	Fill([u8; 4]),
+	/// Blur the area with a gaussian blur of the given strength, in pixels. Small values may
+	/// leave text readable
+	Blur(f32),
+}
+
+impl Default for RedactionStyle {
+	fn default() -> Self {
+		RedactionStyle::Fill([0, 0, 0, 255])
+	}
+}
+
+/// A part of a stored frame that must be covered up before the frame is encoded
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Redaction {
+	/// The area to cover, always given in pixels
+	pub region: CaptureRegion,
+	pub style: RedactionStyle,
+}
 
 /// Align one item (the target) based on a relative position to some point on another item
 /// (the background)
@@ -54,37 +125,298 @@ pub enum Alignment {
 	BottomRight { bottom: u32, right: u32 },
 }
 
+impl Alignment {
+	/// Find the position of the top left corner of a target with the size `target` when it is
+	/// aligned on a background with the size `background`. The position may be negative, or
+	/// past the edge of the background, when the target doesn't fit
+	pub fn position(&self, background: (u32, u32), target: (u32, u32)) -> (i64, i64) {
+		let (background_width, background_height) = (background.0 as i64, background.1 as i64);
+		let (target_width, target_height) = (target.0 as i64, target.1 as i64);
+
+		let centre_x = (background_width - target_width) / 2;
+		let centre_y = (background_height - target_height) / 2;
+		let right_x = |right: u32| background_width - target_width - right as i64;
+		let bottom_y = |bottom: u32| background_height - target_height - bottom as i64;
+
+		match *self {
+			Alignment::TopLeft { top, left } => (left as i64, top as i64),
+			Alignment::TopCentre { top } => (centre_x, top as i64),
+			Alignment::TopRight { top, right } => (right_x(right), top as i64),
+			Alignment::CentreLeft { left } => (left as i64, centre_y),
+			Alignment::CentreCentre => (centre_x, centre_y),
+			Alignment::CentreRight { right } => (right_x(right), centre_y),
+			Alignment::BottomLeft { bottom, left } => (left as i64, bottom_y(bottom)),
+			Alignment::BottomCentre { bottom } => (centre_x, bottom_y(bottom)),
+			Alignment::BottomRight { bottom, right } => (right_x(right), bottom_y(bottom)),
+		}
+	}
+}
+
+/// An image that is blended on top of captured frames, such as a logo
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+	/// The image to draw. It must use an 8 bit RGBA or BGRA texture format, which is the
+	/// case for PNG images loaded by the asset server
+	pub image: Handle<Image>,
+	/// Where the watermark is drawn, relative to the captured frame
+	pub alignment: Alignment,
+	/// How opaque the watermark is, from 0.0 (invisible) to 1.0 (only the alpha channel of
+	/// the watermark image is used)
+	pub opacity: f32,
+	/// Resize the watermark image by this factor before it is drawn. 1.0 draws it at its
+	/// original size
+	pub scale: f32,
+}
+
+impl Watermark {
+	/// Create a fully opaque watermark that is drawn at its original size
+	pub fn new(image: Handle<Image>, alignment: Alignment) -> Self {
+		Self {
+			image,
+			alignment,
+			opacity: 1.0,
+			scale: 1.0,
+		}
+	}
+}
+
+/// A part of the captured view that should be kept, discarding the rest of the frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CaptureRegion {
+	/// A rectangle in pixels, measured from the top left corner of the frame
+	Pixels {
+		x: u32,
+		y: u32,
+		width: u32,
+		height: u32,
+	},
+	/// A rectangle in world space between two opposite corners, such as the bounds of a
+	/// sprite. It is converted to pixels with the tracking camera's `Transform` and
+	/// `OrthographicProjection` when the capture is requested, so the camera should not move
+	/// during a recording that uses it
+	World { min: Vec2, max: Vec2 },
+}
+
+/// Find the pixel that `point` is drawn at in a frame of `size`, rendered by a camera with the
+/// given `transform` and `projection`. The pixel may be outside of the frame. Returns `None`
+/// if the projection doesn't cover any area
+pub(crate) fn world_to_pixel(
+	point: Vec3,
+	transform: &Transform,
+	projection: &OrthographicProjection,
+	size: (u32, u32),
+) -> Option<Vec2> {
+	let view_width = (projection.right - projection.left) * projection.scale;
+	let view_height = (projection.top - projection.bottom) * projection.scale;
+	if view_width <= 0.0 || view_height <= 0.0 {
+		return None;
+	}
+
+	let local = transform.compute_matrix().inverse().transform_point3(point);
+	Some(Vec2::new(
+		(local.x - projection.left * projection.scale) / view_width * size.0 as f32,
+		(projection.top * projection.scale - local.y) / view_height * size.1 as f32,
+	))
+}
+
+impl CaptureRegion {
+	/// Convert this region into pixel coordinates for a frame of `size` that was rendered by a
+	/// camera with the given `transform` and `projection`. Returns `None` if the region is
+	/// entirely outside of the frame
+	pub fn in_pixels(
+		&self,
+		transform: &Transform,
+		projection: &OrthographicProjection,
+		size: (u32, u32),
+	) -> Option<CaptureRegion> {
+		let (min, max) = match *self {
+			CaptureRegion::Pixels {
+				x,
+				y,
+				width,
+				height,
+			} => {
+				if x >= size.0 || y >= size.1 || width == 0 || height == 0 {
+					return None;
+				}
+				return Some(CaptureRegion::Pixels {
+					x,
+					y,
+					width: width.min(size.0 - x),
+					height: height.min(size.1 - y),
+				});
+			}
+			CaptureRegion::World { min, max } => (min.min(max), min.max(max)),
+		};
+
+		// Project every corner, so that the pixel rectangle still covers the whole region when
+		// the camera is rotated
+		let (mut left, mut top) = (f32::MAX, f32::MAX);
+		let (mut right, mut bottom) = (f32::MIN, f32::MIN);
+		for corner in [min, max, Vec2::new(min.x, max.y), Vec2::new(max.x, min.y)] {
+			let (pixel_x, pixel_y) =
+				world_to_pixel(corner.extend(0.0), transform, projection, size)?.into();
+			left = left.min(pixel_x);
+			right = right.max(pixel_x);
+			top = top.min(pixel_y);
+			bottom = bottom.max(pixel_y);
+		}
+
+		let left = left.floor().clamp(0.0, size.0 as f32) as u32;
+		let top = top.floor().clamp(0.0, size.1 as f32) as u32;
+		let right = right.ceil().clamp(0.0, size.0 as f32) as u32;
+		let bottom = bottom.ceil().clamp(0.0, size.1 as f32) as u32;
+		if right <= left || bottom <= top {
+			return None;
+		}
+
+		Some(CaptureRegion::Pixels {
+			x: left,
+			y: top,
+			width: right - left,
+			height: bottom - top,
+		})
+	}
+}
+
+/// Resize captured frames before they are encoded, so that small renders can be shared at a
+/// useful size
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputScale {
+	/// Repeat every pixel this many times in each direction, so pixel art stays sharp. A
+	/// 320x180 frame becomes 960x540 with `Integer(3)`
+	Integer(u32),
+	/// Shrink the frame with a Lanczos filter so that it fits inside this size, keeping its
+	/// aspect ratio. Frames that already fit are left unchanged
+	Fit { width: u32, height: u32 },
+}
+
+/// The pixel data for a single frame. Frames are shared between a recorder's buffer and any
+/// captures taken from it, so the data can't be changed once it has been stored
+pub type FrameData = Arc<Vec<u8>>;
+
+/// A piece of information that can be stamped onto captured frames with a [`TextOverlay`]
+#[cfg(feature = "text-overlay")]
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub enum OverlayText {
+	/// The time at which the frame was shown, in UTC, formatted as `HH:MM:SS.mmm UTC`
+	WallClock,
+	/// The position of the frame within the capture, starting from 0
+	FrameIndex,
+	/// The ID of the recorder that captured the frame
+	RecorderId,
+	/// A fixed piece of text, such as a build version
+	Text(String),
+}
+
+/// Lines of text that are drawn on top of captured frames, such as the time and frame number
+/// for QA clips. Each entry in `lines` is drawn on its own line
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use bevy_capture_media::data::{Alignment, OverlayText, TextOverlay};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let font = std::fs::read("assets/KenneyBlocks.ttf")?;
+/// let overlay = TextOverlay::from_font_bytes(font, Alignment::TopLeft { top: 8, left: 8 })?
+/// 	.with_line(OverlayText::WallClock)
+/// 	.with_line(OverlayText::FrameIndex)
+/// 	.with_line(OverlayText::Text(String::from("v1.2.3")));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "text-overlay")]
+#[derive(Debug, Clone)]
+pub struct TextOverlay {
+	/// The font that the text is drawn with
+	pub font: ab_glyph::FontArc,
+	/// The height of each line of text, in pixels
+	pub size: f32,
+	/// The RGBA colour of the text
+	pub colour: [u8; 4],
+	/// The RGBA colour of a box that is drawn behind the text, to keep it readable
+	pub background: Option<[u8; 4]>,
+	/// Where the text is drawn, relative to the captured frame
+	pub alignment: Alignment,
+	/// The information to draw, one entry per line
+	pub lines: Vec<OverlayText>,
+}
+
+#[cfg(feature = "text-overlay")]
+impl TextOverlay {
+	/// Create an overlay with no lines of white, 16px text on a translucent black background
+	pub fn new(font: ab_glyph::FontArc, alignment: Alignment) -> Self {
+		Self {
+			font,
+			size: 16.0,
+			colour: [255, 255, 255, 255],
+			background: Some([0, 0, 0, 160]),
+			alignment,
+			lines: Vec::new(),
+		}
+	}
+	/// Create an overlay from the bytes of a TrueType or OpenType font
+	pub fn from_font_bytes(
+		font: Vec<u8>,
+		alignment: Alignment,
+	) -> Result<Self, ab_glyph::InvalidFont> {
+		Ok(Self::new(ab_glyph::FontArc::try_from_vec(font)?, alignment))
+	}
+	/// Add a line of text below any existing lines
+	pub fn with_line(mut self, line: OverlayText) -> Self {
+		self.lines.push(line);
+		self
+	}
+}
+
 /// Holds a single frame buffer's worth of pixel data and the amount of time this frame took to render
 #[derive(Clone, Debug)]
 pub struct TextureFrame {
 	/// All of the bytes for one frame
-	pub texture: Vec<u8>,
+	pub texture: FrameData,
 	/// The amount of time it took to render the frame
 	pub frame_time: Duration,
+	/// The part of the frame that should be captured, in pixels, when the recorder was
+	/// following an entity as the frame was rendered
+	pub region: Option<CaptureRegion>,
+	/// The areas of the frame that must be covered up, from entities marked with
+	/// [`RedactInCapture`] as the frame was rendered
+	pub redactions: Vec<Redaction>,
 }
 
 impl TextureFrame {
 	/// Create a new frame with a duration of 0
 	pub fn zeroed(frame: Vec<u8>) -> Self {
 		Self {
-			texture: frame,
+			texture: Arc::new(frame),
 			frame_time: Duration::ZERO,
+			region: None,
+			redactions: Vec::new(),
 		}
 	}
 	/// Create a new frame with a specified duration
 	pub fn with_duration(frame: Vec<u8>, delta: Duration) -> Self {
 		Self {
-			texture: frame,
+			texture: Arc::new(frame),
 			frame_time: delta,
+			region: None,
+			redactions: Vec::new(),
 		}
 	}
 	/// Create a new frame with a duration specified in delta seconds. 1.0 = 1 second.
 	pub fn with_seconds(frame: Vec<u8>, delta: f32) -> Self {
 		Self {
-			texture: frame,
+			texture: Arc::new(frame),
 			frame_time: Duration::from_secs_f32(delta),
+			region: None,
+			redactions: Vec::new(),
 		}
 	}
+	/// Take the bytes for this frame. The bytes are only copied if the frame is still shared
+	/// with a recorder's buffer or another capture
+	pub fn into_bytes(self) -> Vec<u8> {
+		Arc::try_unwrap(self.texture).unwrap_or_else(|shared| shared.as_ref().clone())
+	}
 }
 
 /// What action should a recorder take when it has completed a given command
@@ -95,6 +427,221 @@ pub enum PostCaptureAction {
 	Continue,
 	/// TODO: Implement stop-once-recorded
 	Stop,
+	/// Continue to capture frames into the same frame buffer, leaving the captured frames in
+	/// place so that they can be captured again. The captured frames share their pixel data
+	/// with the buffer, so this is cheap even for long recordings
+	Retain,
+}
+
+/// A window of time within a recorder's frame buffer, measured backwards from the most
+/// recent frame. Any frame that was shown for part of the window is included
+///
+/// ## Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use bevy_capture_media::data::FrameWindow;
+/// // The last 3 seconds of the buffer
+/// FrameWindow::last(Duration::from_secs(3));
+/// // From 8 seconds ago until 2 seconds ago
+/// FrameWindow::between(Duration::from_secs(8), Duration::from_secs(2));
+/// ```
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+pub struct FrameWindow {
+	/// How long ago the window starts. Frames older than this are not captured
+	pub since: Duration,
+	/// How long ago the window ends. Frames more recent than this are not captured
+	pub until: Duration,
+}
+
+impl FrameWindow {
+	/// Select the most recent `duration` worth of frames
+	pub fn last(duration: Duration) -> Self {
+		Self {
+			since: duration,
+			until: Duration::ZERO,
+		}
+	}
+	/// Select the frames that were shown between `since` and `until` ago. The order of the
+	/// arguments does not matter
+	pub fn between(since: Duration, until: Duration) -> Self {
+		Self {
+			since: since.max(until),
+			until: since.min(until),
+		}
+	}
+	/// Find the range of indices in `frames` that fall within this window
+	fn indices(&self, frames: &VecDeque<TextureFrame>) -> Option<(usize, usize)> {
+		let mut range: Option<(usize, usize)> = None;
+		let mut ago = Duration::ZERO;
+		for (index, frame) in frames.iter().enumerate().rev() {
+			let shown_until = ago;
+			ago += frame.frame_time;
+			if shown_until >= self.since {
+				break;
+			}
+			if ago > self.until {
+				range = Some(match range {
+					Some((_, last)) => (index, last),
+					None => (index, index),
+				});
+			}
+		}
+		range
+	}
+}
+
+/// Controls the duration that is stored for each captured frame. Insert this as a resource to
+/// change the timing of every recorder
+///
+/// ## Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use bevy::prelude::*;
+/// # use bevy_capture_media::data::CaptureTimestep;
+/// // Render a trailer at a perfect 60fps, however long each frame takes to render
+/// App::new().insert_resource(CaptureTimestep::Fixed {
+/// 	step: Duration::from_secs(1) / 60,
+/// 	drive_time: true,
+/// });
+/// ```
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+pub enum CaptureTimestep {
+	/// Each frame lasts for as long as it took to render, as measured by `Time::delta`
+	#[default]
+	Variable,
+	/// Each frame lasts for exactly `step`, no matter how long it took to render
+	Fixed {
+		/// The duration of every captured frame. A zero `step` would stop recorders from ever
+		/// evicting frames, so it is ignored and frames are timed as they are with `Variable`
+		step: Duration,
+		/// Also advance Bevy's `Time` resource by exactly `step` each frame, so that anything
+		/// animated with `Time` stays in sync with the captured frames. This is intended for
+		/// offline rendering, as the application will no longer run in real time
+		drive_time: bool,
+	},
+}
+
+impl CaptureTimestep {
+	/// The duration to store for a frame that took `delta` to render
+	pub fn frame_duration(&self, delta: Duration) -> Duration {
+		match *self {
+			CaptureTimestep::Fixed { step, .. } if !step.is_zero() => step,
+			_ => delta,
+		}
+	}
+}
+
+/// A frame of tightly packed, 8 bit RGBA pixels, as seen by a [`FrameFilter`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaFrame {
+	/// The width of the frame, in pixels
+	pub width: u32,
+	/// The height of the frame, in pixels
+	pub height: u32,
+	/// Four bytes per pixel, one row after another
+	pub pixels: Vec<u8>,
+}
+
+/// Describes the frame that a [`FrameFilter`] is being applied to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterContext {
+	/// The recorder that captured the frame
+	pub recorder: RecorderID,
+	/// The position of the frame within the capture, starting from 0. For single frames, this
+	/// is the position of the frame in the recorder's buffer
+	pub index: usize,
+	/// How long before the capture was requested that this frame stopped being shown
+	pub ago: Duration,
+}
+
+/// Transforms a captured frame before it is encoded. Filters run in the same async task as the
+/// encoder, so they won't block the app while they run. A filter may change the size of the
+/// frame
+///
+/// ## Example
+///
+/// ```rust
+/// # use bevy_capture_media::data::{FilterContext, FrameFilter, RgbaFrame};
+/// /// Swap the red and blue channels of every pixel
+/// struct SwapRedBlue;
+///
+/// impl FrameFilter for SwapRedBlue {
+/// 	fn apply(&self, mut frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+/// 		for pixel in frame.pixels.chunks_exact_mut(4) {
+/// 			pixel.swap(0, 2);
+/// 		}
+/// 		frame
+/// 	}
+/// }
+/// ```
+pub trait FrameFilter: Send + Sync + 'static {
+	fn apply(&self, frame: RgbaFrame, context: &FilterContext) -> RgbaFrame;
+}
+
+/// A list of [`FrameFilter`]s that are applied one after another, in the order they were added
+#[derive(Clone, Default)]
+pub struct FilterChain(Vec<Arc<dyn FrameFilter>>);
+
+impl FilterChain {
+	/// Create a chain with no filters
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// Add a filter to the end of the chain
+	pub fn with<F: FrameFilter>(mut self, filter: F) -> Self {
+		self.push(filter);
+		self
+	}
+	/// Add a filter to the end of the chain
+	pub fn push<F: FrameFilter>(&mut self, filter: F) {
+		self.0.push(Arc::new(filter));
+	}
+	/// Add every filter from `other` to the end of the chain
+	pub fn extend(&mut self, other: &FilterChain) {
+		self.0.extend(other.0.iter().cloned());
+	}
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+	/// Run `frame` through every filter in the chain. Returns `None` if a filter produces a
+	/// frame with the wrong number of pixels for its size, before it reaches the next filter
+	pub fn apply(&self, frame: RgbaFrame, context: &FilterContext) -> Option<RgbaFrame> {
+		self.0.iter().try_fold(frame, |frame, filter| {
+			let frame = filter.apply(frame, context);
+			if frame.pixels.len() != frame.width as usize * frame.height as usize * 4 {
+				log::error!(
+					"A frame filter produced a frame with the wrong number of pixels, skipping frame {}",
+					context.index
+				);
+				return None;
+			}
+			Some(frame)
+		})
+	}
+}
+
+impl Debug for FilterChain {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("FilterChain").field(&self.0.len()).finish()
+	}
+}
+
+/// Chooses which of the frames stored by a recorder is captured for a single frame
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+pub enum FrameSelector {
+	/// The most recent frame
+	#[default]
+	Latest,
+	/// The frame that was being shown the given amount of time before the most recent frame.
+	/// The oldest frame is used if the buffer doesn't go back that far
+	Ago(Duration),
+	/// The frame at the given position in the buffer, where 0 is the oldest stored frame
+	Index(usize),
 }
 
 #[derive(Debug)]
@@ -102,7 +649,70 @@ pub struct ActiveRecorder {
 	pub tracker: Entity,
 	pub target_handle: Handle<Image>,
 	pub target_duration: Duration,
+	/// The most recent frames, oldest first. Evicting a frame only drops the buffer's
+	/// reference to it; captures that are still using the frame keep its data alive
 	pub frames: VecDeque<TextureFrame>,
+	/// Filters that are applied to every capture from this recorder, before the filters of
+	/// the capture request itself
+	pub filters: FilterChain,
+}
+
+impl ActiveRecorder {
+	/// Find the stored frame that matches `selector`
+	pub fn frame(&self, selector: FrameSelector) -> Option<&TextureFrame> {
+		self.frame_index(selector)
+			.and_then(|index| self.frames.get(index))
+	}
+
+	/// Find the position in the buffer of the stored frame that matches `selector`
+	pub fn frame_index(&self, selector: FrameSelector) -> Option<usize> {
+		if self.frames.is_empty() {
+			return None;
+		}
+
+		match selector {
+			FrameSelector::Latest => Some(self.frames.len() - 1),
+			FrameSelector::Index(index) => (index < self.frames.len()).then_some(index),
+			FrameSelector::Ago(ago) => {
+				let mut shown_until = Duration::ZERO;
+				for (index, frame) in self.frames.iter().enumerate().rev() {
+					shown_until += frame.frame_time;
+					if shown_until > ago {
+						return Some(index);
+					}
+				}
+				Some(0)
+			}
+		}
+	}
+
+	/// Take the frames that should be captured for a recording. The whole buffer is used
+	/// unless a `window` is given. With [`PostCaptureAction::Retain`] the frames are copied and
+	/// the buffer is left in place, otherwise the buffer is drained
+	pub fn take_frames(
+		&mut self,
+		window: Option<FrameWindow>,
+		and_then: PostCaptureAction,
+	) -> VecDeque<TextureFrame> {
+		let (first, last) = match window {
+			Some(window) => match window.indices(&self.frames) {
+				Some(range) => range,
+				None => return VecDeque::new(),
+			},
+			None if self.frames.is_empty() => return VecDeque::new(),
+			None => (0, self.frames.len() - 1),
+		};
+
+		match and_then {
+			PostCaptureAction::Retain => self.frames.range(first..=last).cloned().collect(),
+			PostCaptureAction::Continue | PostCaptureAction::Stop => {
+				let mut frames = std::mem::take(&mut self.frames);
+				frames.truncate(last + 1);
+				frames.drain(..first);
+				frames
+			}
+		}
+	}
 }
 
 #[derive(Default, Debug)]
@@ -186,6 +796,18 @@ pub struct StartTrackingCamera {
 	pub cam_entity: Entity,
 	pub tracking_id: RecorderID,
 	pub length: Duration,
+	/// The render layers that the tracking camera can see, such as every layer except the
+	/// one used for debug gizmos. Layers can be changed later by inserting `RenderLayers` on
+	/// the tracking camera, available as `ActiveRecorder::tracker`
+	///
+	/// ## `None`
+	///
+	/// Use the same render layers as the tracked camera
+	pub render_layers: Option<RenderLayers>,
+	/// Clear the tracking camera to full transparency instead of the tracked camera's clear
+	/// colour, so that captures only contain what was drawn. Formats with an alpha channel keep
+	/// the transparency, and GIF recordings mark transparent pixels with a transparent index
+	pub transparent_background: bool,
 }
 
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
@@ -197,7 +819,7 @@ pub struct StopTrackingCamera {
 /// information. This will be the most recent frame already stored when
 /// the event is processed, rather than the next frame to be stored after
 /// the event is processed.
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CaptureFrame<CaptureType> {
 	/// The identifier for the camera tracker that should capture a frame
 	pub tracking_id: RecorderID,
@@ -218,6 +840,19 @@ pub struct CaptureFrame<CaptureType> {
 	/// the platform - if you rely on a consistent format across platforms, you must provide
 	/// a `path`.
 	pub path: Option<PathBuf>,
+	/// Which of the stored frames should be captured. Defaults to the most recent frame
+	pub frame: FrameSelector,
+	/// Draw lines of text on top of the frame
+	#[cfg(feature = "text-overlay")]
+	pub text: Option<TextOverlay>,
+	/// Only keep part of the frame. Cropping happens before any filters run
+	pub crop: Option<CaptureRegion>,
+	/// Resize the frame before it is encoded. Scaling happens after any filters have run, and
+	/// before the watermark and text are drawn
+	pub scale: Option<OutputScale>,
+	/// Filters that are applied to the frame before it is encoded, after any filters that
+	/// belong to the recorder
+	pub filters: FilterChain,
 	/// Determines what the camera tracker should do after recording this frame
 	pub and_then: PostCaptureAction,
 	/// Define the type of capture to use (e.g. PNG). Some capture types may provide more
@@ -225,10 +860,29 @@ pub struct CaptureFrame<CaptureType> {
 	pub capture_type: CaptureType,
 }
 
+impl<CaptureType> CaptureFrame<CaptureType> {
+	/// Capture the most recent frame from the recorder identified by `tracking_id`, with a
+	/// default file name and no additional filters
+	pub fn new(tracking_id: RecorderID, capture_type: CaptureType) -> Self {
+		Self {
+			tracking_id,
+			path: None,
+			frame: FrameSelector::Latest,
+			#[cfg(feature = "text-overlay")]
+			text: None,
+			crop: None,
+			scale: None,
+			filters: FilterChain::default(),
+			and_then: PostCaptureAction::Continue,
+			capture_type,
+		}
+	}
+}
+
 /// Request that the current frame buffer is converted into the specified `CaptureType` and
 /// saved. Encoding the frame buffer usually takes some amount of time on most platforms, which
 /// will happen asynchronously
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CaptureRecording<CaptureType> {
 	/// The identifier for the camera tracker that should capture a frame
 	pub tracking_id: RecorderID,
@@ -249,6 +903,35 @@ pub struct CaptureRecording<CaptureType> {
 	/// the platform - if you rely on a consistent format across platforms, you must provide
 	/// a `path`.
 	pub path: Option<PathBuf>,
+	/// Resample the frame buffer to a constant number of frames per second before it is
+	/// encoded. Frames are repeated or skipped based on how long each one was shown for, and
+	/// every output frame has the same duration.
+	///
+	/// ## `None`
+	///
+	/// Frames keep the duration that they were rendered with, which will vary with the
+	/// performance of the application
+	pub frame_rate: Option<u32>,
+	/// Only capture the frames that fall within a window of time, such as the last few
+	/// seconds of the buffer
+	///
+	/// ## `None`
+	///
+	/// Every frame in the buffer is captured
+	pub window: Option<FrameWindow>,
+	/// Draw a watermark on top of every frame of the recording
+	pub watermark: Option<Watermark>,
+	/// Draw lines of text on top of every frame of the recording
+	#[cfg(feature = "text-overlay")]
+	pub text: Option<TextOverlay>,
+	/// Only keep part of every frame of the recording. Cropping happens before any filters run
+	pub crop: Option<CaptureRegion>,
+	/// Resize every frame of the recording before it is encoded. Scaling happens after any
+	/// filters have run, and before the watermark and text are drawn
+	pub scale: Option<OutputScale>,
+	/// Filters that are applied to every frame of the recording before it is encoded, after
+	/// any filters that belong to the recorder
+	pub filters: FilterChain,
 	/// Determines what the camera tracker should do after recording this frame
 	pub and_then: PostCaptureAction,
 	/// Define the type of capture to use (e.g. PNG). Some capture types may provide more
@@ -256,6 +939,27 @@ pub struct CaptureRecording<CaptureType> {
 	pub capture_type: CaptureType,
 }
 
+impl<CaptureType> CaptureRecording<CaptureType> {
+	/// Capture every frame stored by the recorder identified by `tracking_id`, with a default
+	/// file name and no additional filters
+	pub fn new(tracking_id: RecorderID, capture_type: CaptureType) -> Self {
+		Self {
+			tracking_id,
+			path: None,
+			frame_rate: None,
+			window: None,
+			watermark: None,
+			#[cfg(feature = "text-overlay")]
+			text: None,
+			crop: None,
+			scale: None,
+			filters: FilterChain::default(),
+			and_then: PostCaptureAction::Continue,
+			capture_type,
+		}
+	}
+}
+
 // -- Handlers --
 
 /// Dispatch events to control media capture. Works as a system param. The methods available
@@ -276,10 +980,31 @@ pub struct CaptureRecording<CaptureType> {
 /// ```
 #[derive(SystemParam)]
 pub struct MediaCapture<'w, 's> {
+	#[cfg(feature = "apng")]
+	capture_apng: EventWriter<'w, 's, crate::formats::apng::CaptureApngRecording>,
+	#[cfg(feature = "video-av1")]
+	capture_av1: EventWriter<'w, 's, crate::formats::av1::CaptureAv1Recording>,
 	#[cfg(feature = "gif")]
 	capture_gif: EventWriter<'w, 's, crate::formats::gif::CaptureGifRecording>,
+	#[cfg(feature = "jpeg")]
+	capture_jpeg: EventWriter<'w, 's, crate::formats::jpeg::SaveJpegFile>,
+	#[cfg(feature = "mjpeg")]
+	capture_mjpeg: EventWriter<'w, 's, crate::formats::mjpeg::CaptureMjpegRecording>,
+	#[cfg(all(feature = "pipe", not(target_arch = "wasm32")))]
+	capture_pipe: EventWriter<'w, 's, crate::formats::pipe::CapturePipeRecording>,
 	#[cfg(feature = "png")]
 	capture_png: EventWriter<'w, 's, crate::formats::png::SavePngFile>,
+	#[cfg(any(feature = "qoi", feature = "tga", feature = "bmp", feature = "tiff"))]
+	capture_image: EventWriter<'w, 's, crate::formats::image_file::SaveImageFile>,
+	#[cfg(all(feature = "sequence", not(target_arch = "wasm32")))]
+	capture_sequence: EventWriter<'w, 's, crate::formats::sequence::CaptureSequenceRecording>,
+	#[cfg(feature = "sprite-sheet")]
+	capture_sprite_sheet:
+		EventWriter<'w, 's, crate::formats::sprite_sheet::CaptureSpriteSheetRecording>,
+	#[cfg(feature = "webp")]
+	capture_webp: EventWriter<'w, 's, crate::formats::webp::CaptureWebpRecording>,
+	#[cfg(feature = "y4m")]
+	capture_y4m: EventWriter<'w, 's, crate::formats::y4m::CaptureY4mRecording>,
 
 	start_tracking: EventWriter<'w, 's, StartTrackingCamera>,
 	stop_tracking: EventWriter<'w, 's, StopTrackingCamera>,
@@ -305,6 +1030,55 @@ impl<'w, 's> MediaCapture<'w, 's> {
 			tracking_id,
 			cam_entity: target,
 			length,
+			render_layers: None,
+			transparent_background: false,
+		});
+	}
+
+	/// Start to capture frames for the given camera, only rendering entities on the given
+	/// `render_layers`. Anything that the tracked camera can see on other layers, such as
+	/// debug gizmos, will not appear in captures
+	///
+	/// ```ignore
+	/// // Capture everything except layer 31
+	/// media.start_tracking_camera_with_layers(
+	/// 	1,
+	/// 	camera,
+	/// 	Duration::from_secs(5),
+	/// 	RenderLayers::all().without(31),
+	/// );
+	/// ```
+	pub fn start_tracking_camera_with_layers(
+		&mut self,
+		tracking_id: RecorderID,
+		target: Entity,
+		length: Duration,
+		render_layers: RenderLayers,
+	) {
+		self.start_tracking.send(StartTrackingCamera {
+			tracking_id,
+			cam_entity: target,
+			length,
+			render_layers: Some(render_layers),
+			transparent_background: false,
+		});
+	}
+
+	/// Start to capture frames for the given camera with a transparent background, for
+	/// capturing sprites and UI without the scene behind them. Use a format that keeps the
+	/// alpha channel, such as PNG, APNG, WebP or GIF
+	pub fn start_tracking_transparent_camera(
+		&mut self,
+		tracking_id: RecorderID,
+		target: Entity,
+		length: Duration,
+	) {
+		self.start_tracking.send(StartTrackingCamera {
+			tracking_id,
+			cam_entity: target,
+			length,
+			render_layers: None,
+			transparent_background: true,
 		});
 	}
 
@@ -322,12 +1096,10 @@ impl<'w, 's> MediaCapture<'w, 's> {
 	/// stored frames into a gif, and save it with a default name
 	#[cfg(feature = "gif")]
 	pub fn capture_gif(&mut self, tracking_id: RecorderID) {
-		self.capture_gif.send(CaptureRecording {
+		self.capture_gif.send(CaptureRecording::new(
 			tracking_id,
-			and_then: PostCaptureAction::Continue,
-			path: None,
-			capture_type: crate::formats::gif::RecordGif,
-		});
+			crate::formats::gif::RecordGif,
+		));
 	}
 
 	/// Request that the recorder identified by `tracking_id` encodes its
@@ -335,10 +1107,52 @@ impl<'w, 's> MediaCapture<'w, 's> {
 	#[cfg(feature = "gif")]
 	pub fn capture_gif_with_path<P: AsRef<Path>>(&mut self, tracking_id: RecorderID, path: P) {
 		self.capture_gif.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, crate::formats::gif::RecordGif)
+		});
+	}
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into an animated PNG, and save it with a default name
+	#[cfg(feature = "apng")]
+	pub fn capture_apng(&mut self, tracking_id: RecorderID) {
+		self.capture_apng.send(CaptureRecording::new(
 			tracking_id,
-			and_then: PostCaptureAction::Continue,
+			crate::formats::apng::RecordApng,
+		));
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into an animated PNG, and save it to a specified path
+	#[cfg(feature = "apng")]
+	pub fn capture_apng_with_path<P: AsRef<Path>>(&mut self, tracking_id: RecorderID, path: P) {
+		self.capture_apng.send(CaptureRecording {
 			path: Some(path.as_ref().to_path_buf()),
-			capture_type: crate::formats::gif::RecordGif,
+			..CaptureRecording::new(tracking_id, crate::formats::apng::RecordApng)
+		});
+	}
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// most recently stored frame into a JPEG image with the given quality
+	/// (1 - 100), and save it with a default name
+	#[cfg(feature = "jpeg")]
+	pub fn capture_jpeg(&mut self, tracking_id: RecorderID, quality: u8) {
+		self.capture_jpeg.send(CaptureFrame::new(
+			tracking_id,
+			crate::formats::jpeg::SaveJpeg { quality },
+		));
+	}
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// most recently stored frame into a JPEG image with the given quality
+	/// (1 - 100), and save it to a specified path
+	#[cfg(feature = "jpeg")]
+	pub fn capture_jpeg_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		path: P,
+		quality: u8,
+	) {
+		self.capture_jpeg.send(CaptureFrame {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureFrame::new(tracking_id, crate::formats::jpeg::SaveJpeg { quality })
 		});
 	}
 	/// Request that the recorder identified by `tracking_id` encodes its
@@ -346,12 +1160,10 @@ impl<'w, 's> MediaCapture<'w, 's> {
 	/// default name
 	#[cfg(feature = "png")]
 	pub fn capture_png(&mut self, tracking_id: RecorderID) {
-		self.capture_png.send(CaptureFrame {
+		self.capture_png.send(CaptureFrame::new(
 			tracking_id,
-			and_then: PostCaptureAction::Continue,
-			path: None,
-			capture_type: crate::formats::png::SavePng::Basic,
-		});
+			crate::formats::png::SavePng::Basic,
+		));
 	}
 	/// Request that the recorder identified by `tracking_id` encodes its
 	/// most recently stored frame into a PNG image, and save it to a
@@ -359,10 +1171,582 @@ impl<'w, 's> MediaCapture<'w, 's> {
 	#[cfg(feature = "png")]
 	pub fn capture_png_with_path<P: AsRef<Path>>(&mut self, tracking_id: RecorderID, path: P) {
 		self.capture_png.send(CaptureFrame {
-			tracking_id,
-			and_then: PostCaptureAction::Continue,
 			path: Some(path.as_ref().to_path_buf()),
-			capture_type: crate::formats::png::SavePng::Basic,
+			..CaptureFrame::new(tracking_id, crate::formats::png::SavePng::Basic)
 		})
 	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// most recently stored frame into an image with the given format, and
+	/// save it with a default name. The feature for the requested format
+	/// (e.g. `qoi`) must be enabled
+	#[cfg(any(feature = "qoi", feature = "tga", feature = "bmp", feature = "tiff"))]
+	pub fn capture_image(&mut self, tracking_id: RecorderID, format: image::ImageFormat) {
+		self.capture_image.send(CaptureFrame::new(
+			tracking_id,
+			crate::formats::image_file::SaveImage(format),
+		));
+	}
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// most recently stored frame into an image with the given format, and
+	/// save it to a specified path. The feature for the requested format
+	/// (e.g. `qoi`) must be enabled
+	#[cfg(any(feature = "qoi", feature = "tga", feature = "bmp", feature = "tiff"))]
+	pub fn capture_image_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		path: P,
+		format: image::ImageFormat,
+	) {
+		self.capture_image.send(CaptureFrame {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureFrame::new(tracking_id, crate::formats::image_file::SaveImage(format))
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` writes each of
+	/// its stored frames as a numbered PNG, along with a manifest of frame
+	/// times, into a directory with a default name
+	#[cfg(all(feature = "sequence", not(target_arch = "wasm32")))]
+	pub fn capture_sequence(&mut self, tracking_id: RecorderID) {
+		self.capture_sequence.send(CaptureRecording::new(
+			tracking_id,
+			crate::formats::sequence::RecordSequence,
+		));
+	}
+
+	/// Request that the recorder identified by `tracking_id` writes each of
+	/// its stored frames as a numbered PNG, along with a manifest of frame
+	/// times, into the specified directory
+	#[cfg(all(feature = "sequence", not(target_arch = "wasm32")))]
+	pub fn capture_sequence_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		directory: P,
+	) {
+		self.capture_sequence.send(CaptureRecording {
+			path: Some(directory.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, crate::formats::sequence::RecordSequence)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` writes each of
+	/// its stored frames from the given `window` as a numbered PNG, along
+	/// with a manifest of frame times, into the specified directory. The
+	/// stored frames are kept, so this can be used to let a player pick a
+	/// screenshot from the last few seconds and capture it with a
+	/// [`FrameSelector`]
+	#[cfg(all(feature = "sequence", not(target_arch = "wasm32")))]
+	pub fn capture_sequence_window<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		directory: P,
+		window: FrameWindow,
+	) {
+		self.capture_sequence.send(CaptureRecording {
+			path: Some(directory.as_ref().to_path_buf()),
+			window: Some(window),
+			and_then: PostCaptureAction::Retain,
+			..CaptureRecording::new(tracking_id, crate::formats::sequence::RecordSequence)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` lays its
+	/// stored frames out on a PNG sprite sheet with a JSON descriptor,
+	/// and save it with a default name
+	#[cfg(feature = "sprite-sheet")]
+	pub fn capture_sprite_sheet(
+		&mut self,
+		tracking_id: RecorderID,
+		settings: crate::formats::sprite_sheet::RecordSpriteSheet,
+	) {
+		self.capture_sprite_sheet
+			.send(CaptureRecording::new(tracking_id, settings));
+	}
+
+	/// Request that the recorder identified by `tracking_id` lays its
+	/// stored frames out on a PNG sprite sheet with a JSON descriptor,
+	/// and save it to a specified path. The descriptor is saved
+	/// alongside the sheet with a `.json` extension
+	#[cfg(feature = "sprite-sheet")]
+	pub fn capture_sprite_sheet_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		path: P,
+		settings: crate::formats::sprite_sheet::RecordSpriteSheet,
+	) {
+		self.capture_sprite_sheet.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, settings)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into a lossless animated WebP, and save it with a
+	/// default name
+	#[cfg(feature = "webp")]
+	pub fn capture_webp(&mut self, tracking_id: RecorderID) {
+		self.capture_webp.send(CaptureRecording::new(
+			tracking_id,
+			crate::formats::webp::RecordWebp,
+		));
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into a lossless animated WebP, and save it to a
+	/// specified path
+	#[cfg(feature = "webp")]
+	pub fn capture_webp_with_path<P: AsRef<Path>>(&mut self, tracking_id: RecorderID, path: P) {
+		self.capture_webp.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, crate::formats::webp::RecordWebp)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` writes its
+	/// stored frames as an uncompressed Y4M video, and save it with a
+	/// default name
+	#[cfg(feature = "y4m")]
+	pub fn capture_y4m(&mut self, tracking_id: RecorderID) {
+		self.capture_y4m.send(CaptureRecording::new(
+			tracking_id,
+			crate::formats::y4m::RecordY4m,
+		));
+	}
+
+	/// Request that the recorder identified by `tracking_id` writes its
+	/// stored frames as an uncompressed Y4M video, and save it to a
+	/// specified path
+	#[cfg(feature = "y4m")]
+	pub fn capture_y4m_with_path<P: AsRef<Path>>(&mut self, tracking_id: RecorderID, path: P) {
+		self.capture_y4m.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, crate::formats::y4m::RecordY4m)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into an AV1 video using the given encoder settings,
+	/// and save it with a default name
+	#[cfg(feature = "video-av1")]
+	pub fn capture_video(
+		&mut self,
+		tracking_id: RecorderID,
+		settings: crate::formats::av1::RecordAv1,
+	) {
+		self.capture_av1
+			.send(CaptureRecording::new(tracking_id, settings));
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into an AV1 video using the given encoder settings,
+	/// and save it to a specified path
+	#[cfg(feature = "video-av1")]
+	pub fn capture_video_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		path: P,
+		settings: crate::formats::av1::RecordAv1,
+	) {
+		self.capture_av1.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, settings)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into a Motion-JPEG AVI video using the given settings,
+	/// and save it with a default name
+	#[cfg(feature = "mjpeg")]
+	pub fn capture_mjpeg(
+		&mut self,
+		tracking_id: RecorderID,
+		settings: crate::formats::mjpeg::RecordMjpeg,
+	) {
+		self.capture_mjpeg
+			.send(CaptureRecording::new(tracking_id, settings));
+	}
+
+	/// Request that the recorder identified by `tracking_id` encodes its
+	/// stored frames into a Motion-JPEG AVI video using the given settings,
+	/// and save it to a specified path
+	#[cfg(feature = "mjpeg")]
+	pub fn capture_mjpeg_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		path: P,
+		settings: crate::formats::mjpeg::RecordMjpeg,
+	) {
+		self.capture_mjpeg.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, settings)
+		});
+	}
+
+	/// Request that the recorder identified by `tracking_id` streams its
+	/// stored frames to an external program. A
+	/// [`PipeRecordingFinished`](crate::formats::pipe::PipeRecordingFinished)
+	/// event is sent once the program exits
+	#[cfg(all(feature = "pipe", not(target_arch = "wasm32")))]
+	pub fn capture_pipe(
+		&mut self,
+		tracking_id: RecorderID,
+		settings: crate::formats::pipe::RecordPipe,
+	) {
+		self.capture_pipe
+			.send(CaptureRecording::new(tracking_id, settings));
+	}
+
+	/// Request that the recorder identified by `tracking_id` streams its
+	/// stored frames to an external program, substituting `path` for any
+	/// `{path}` placeholders in its arguments. A
+	/// [`PipeRecordingFinished`](crate::formats::pipe::PipeRecordingFinished)
+	/// event is sent once the program exits
+	#[cfg(all(feature = "pipe", not(target_arch = "wasm32")))]
+	pub fn capture_pipe_with_path<P: AsRef<Path>>(
+		&mut self,
+		tracking_id: RecorderID,
+		path: P,
+		settings: crate::formats::pipe::RecordPipe,
+	) {
+		self.capture_pipe.send(CaptureRecording {
+			path: Some(path.as_ref().to_path_buf()),
+			..CaptureRecording::new(tracking_id, settings)
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A buffer of `count` frames that were each shown for 100ms
+	fn frames(count: usize) -> VecDeque<TextureFrame> {
+		(0..count)
+			.map(|_| TextureFrame::with_duration(Vec::new(), Duration::from_millis(100)))
+			.collect()
+	}
+
+	#[test]
+	fn frame_window_selects_the_most_recent_frames() {
+		let window = FrameWindow::last(Duration::from_millis(300));
+		assert_eq!(window.indices(&frames(10)), Some((7, 9)));
+	}
+
+	#[test]
+	fn frame_window_selects_frames_between_two_times() {
+		let window = FrameWindow::between(Duration::from_millis(800), Duration::from_millis(200));
+		assert_eq!(window.indices(&frames(10)), Some((2, 7)));
+	}
+
+	#[test]
+	fn frame_window_is_clamped_to_the_buffer() {
+		let window = FrameWindow::last(Duration::from_secs(5));
+		assert_eq!(window.indices(&frames(10)), Some((0, 9)));
+	}
+
+	#[test]
+	fn frame_window_past_the_buffer_is_empty() {
+		let window = FrameWindow::between(Duration::from_secs(5), Duration::from_secs(2));
+		assert_eq!(window.indices(&frames(10)), None);
+		assert_eq!(
+			FrameWindow::last(Duration::from_secs(1)).indices(&frames(0)),
+			None
+		);
+	}
+
+	/// A recorder holding a buffer of `count` frames that were each shown for 100ms
+	fn recorder(count: usize) -> ActiveRecorder {
+		ActiveRecorder {
+			tracker: Entity::from_raw(0),
+			target_handle: Handle::default(),
+			target_duration: Duration::from_secs(1),
+			frames: frames(count),
+			filters: FilterChain::default(),
+		}
+	}
+
+	#[test]
+	fn windowed_capture_drains_the_whole_buffer() {
+		let mut recorder = recorder(10);
+		let window = FrameWindow::last(Duration::from_millis(300));
+		let taken = recorder.take_frames(Some(window), PostCaptureAction::Continue);
+		assert_eq!(taken.len(), 3);
+		assert!(recorder.frames.is_empty());
+	}
+
+	#[test]
+	fn retained_capture_shares_the_buffer() {
+		let mut recorder = recorder(10);
+		let window = FrameWindow::last(Duration::from_millis(300));
+		let taken = recorder.take_frames(Some(window), PostCaptureAction::Retain);
+		assert_eq!(taken.len(), 3);
+		assert_eq!(recorder.frames.len(), 10);
+		for (taken, stored) in taken.iter().zip(recorder.frames.range(7..)) {
+			assert!(Arc::ptr_eq(&taken.texture, &stored.texture));
+		}
+	}
+
+	#[test]
+	fn frame_selector_looks_back_through_the_buffer() {
+		let recorder = recorder(10);
+		assert_eq!(recorder.frame_index(FrameSelector::Latest), Some(9));
+		assert_eq!(
+			recorder.frame_index(FrameSelector::Ago(Duration::ZERO)),
+			Some(9)
+		);
+		assert_eq!(
+			recorder.frame_index(FrameSelector::Ago(Duration::from_millis(100))),
+			Some(8)
+		);
+		assert_eq!(
+			recorder.frame_index(FrameSelector::Ago(Duration::from_millis(250))),
+			Some(7)
+		);
+		assert_eq!(
+			recorder.frame_index(FrameSelector::Ago(Duration::from_secs(5))),
+			Some(0)
+		);
+	}
+
+	#[test]
+	fn frame_selector_indexes_from_the_oldest_frame() {
+		let recorder = recorder(10);
+		assert_eq!(recorder.frame_index(FrameSelector::Index(0)), Some(0));
+		assert_eq!(recorder.frame_index(FrameSelector::Index(3)), Some(3));
+		assert_eq!(recorder.frame_index(FrameSelector::Index(10)), None);
+	}
+
+	#[test]
+	fn frame_selector_finds_nothing_in_an_empty_buffer() {
+		let recorder = recorder(0);
+		assert_eq!(recorder.frame_index(FrameSelector::Latest), None);
+		assert_eq!(
+			recorder.frame_index(FrameSelector::Ago(Duration::ZERO)),
+			None
+		);
+		assert_eq!(recorder.frame_index(FrameSelector::Index(0)), None);
+	}
+
+	#[test]
+	fn alignment_offsets_from_each_edge() {
+		let (background, target) = ((100, 50), (20, 10));
+		assert_eq!(
+			Alignment::TopLeft { top: 2, left: 3 }.position(background, target),
+			(3, 2)
+		);
+		assert_eq!(
+			Alignment::TopCentre { top: 0 }.position(background, target),
+			(40, 0)
+		);
+		assert_eq!(
+			Alignment::CentreRight { right: 4 }.position(background, target),
+			(76, 20)
+		);
+		assert_eq!(
+			Alignment::BottomRight {
+				bottom: 5,
+				right: 4
+			}
+			.position(background, target),
+			(76, 35)
+		);
+		assert_eq!(
+			Alignment::BottomLeft { bottom: 5, left: 3 }.position(background, target),
+			(3, 35)
+		);
+	}
+
+	#[test]
+	fn alignment_can_place_a_target_outside_of_the_background() {
+		assert_eq!(
+			Alignment::CentreCentre.position((100, 50), (120, 60)),
+			(-10, -5)
+		);
+		assert_eq!(
+			Alignment::BottomRight {
+				bottom: 0,
+				right: 0
+			}
+			.position((10, 10), (20, 20)),
+			(-10, -10)
+		);
+	}
+
+	/// A camera at the origin that shows 100 by 50 world units
+	fn projection() -> OrthographicProjection {
+		OrthographicProjection {
+			left: -50.0,
+			right: 50.0,
+			bottom: -25.0,
+			top: 25.0,
+			..Default::default()
+		}
+	}
+
+	/// Rendered at two pixels per world unit
+	const FRAME_SIZE: (u32, u32) = (200, 100);
+
+	#[test]
+	fn world_region_is_converted_to_pixels() {
+		let expected = Some(CaptureRegion::Pixels {
+			x: 50,
+			y: 0,
+			width: 50,
+			height: 50,
+		});
+		let region = CaptureRegion::World {
+			min: Vec2::new(-25.0, 0.0),
+			max: Vec2::new(0.0, 25.0),
+		};
+		assert_eq!(
+			region.in_pixels(&Transform::default(), &projection(), FRAME_SIZE),
+			expected
+		);
+
+		// The corners can be given in any order
+		let swapped = CaptureRegion::World {
+			min: Vec2::new(0.0, 25.0),
+			max: Vec2::new(-25.0, 0.0),
+		};
+		assert_eq!(
+			swapped.in_pixels(&Transform::default(), &projection(), FRAME_SIZE),
+			expected
+		);
+	}
+
+	#[test]
+	fn world_region_follows_the_camera() {
+		let region = CaptureRegion::World {
+			min: Vec2::new(0.0, 0.0),
+			max: Vec2::new(25.0, 25.0),
+		};
+		assert_eq!(
+			region.in_pixels(
+				&Transform::from_xyz(50.0, 0.0, 0.0),
+				&projection(),
+				FRAME_SIZE
+			),
+			Some(CaptureRegion::Pixels {
+				x: 0,
+				y: 0,
+				width: 50,
+				height: 50,
+			})
+		);
+	}
+
+	#[test]
+	fn regions_are_clipped_to_the_frame() {
+		let world = CaptureRegion::World {
+			min: Vec2::new(40.0, -40.0),
+			max: Vec2::new(60.0, -20.0),
+		};
+		assert_eq!(
+			world.in_pixels(&Transform::default(), &projection(), FRAME_SIZE),
+			Some(CaptureRegion::Pixels {
+				x: 180,
+				y: 90,
+				width: 20,
+				height: 10,
+			})
+		);
+
+		let pixels = CaptureRegion::Pixels {
+			x: 190,
+			y: 0,
+			width: 20,
+			height: 10,
+		};
+		assert_eq!(
+			pixels.in_pixels(&Transform::default(), &projection(), FRAME_SIZE),
+			Some(CaptureRegion::Pixels {
+				x: 190,
+				y: 0,
+				width: 10,
+				height: 10,
+			})
+		);
+	}
+
+	#[test]
+	fn regions_outside_of_the_frame_are_rejected() {
+		let world = CaptureRegion::World {
+			min: Vec2::new(60.0, 0.0),
+			max: Vec2::new(70.0, 10.0),
+		};
+		let pixels = CaptureRegion::Pixels {
+			x: 200,
+			y: 0,
+			width: 10,
+			height: 10,
+		};
+		let empty = CaptureRegion::Pixels {
+			x: 0,
+			y: 0,
+			width: 0,
+			height: 10,
+		};
+		for region in [world, pixels, empty] {
+			assert_eq!(
+				region.in_pixels(&Transform::default(), &projection(), FRAME_SIZE),
+				None
+			);
+		}
+	}
+
+	/// A broken filter that loses the last pixel of every frame
+	struct DropLastPixel;
+
+	impl FrameFilter for DropLastPixel {
+		fn apply(&self, mut frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+			frame.pixels.truncate(frame.pixels.len().saturating_sub(4));
+			frame
+		}
+	}
+
+	#[test]
+	fn filter_chain_stops_at_a_frame_with_the_wrong_number_of_pixels() {
+		let context = FilterContext {
+			recorder: 1,
+			index: 0,
+			ago: Duration::ZERO,
+		};
+		let frame = || RgbaFrame {
+			width: 2,
+			height: 2,
+			pixels: vec![0; 16],
+		};
+		let crop = crate::filters::Crop {
+			x: 0,
+			y: 0,
+			width: 1,
+			height: 1,
+		};
+
+		let cropped = FilterChain::new().with(crop).apply(frame(), &context);
+		assert_eq!(cropped.map(|frame| frame.pixels.len()), Some(4));
+
+		// The crop would panic if it was given the broken frame
+		let broken = FilterChain::new()
+			.with(DropLastPixel)
+			.with(crop)
+			.apply(frame(), &context);
+		assert!(broken.is_none());
+	}
+
+	#[test]
+	fn fixed_timestep_ignores_a_zero_step() {
+		let delta = Duration::from_millis(16);
+		let fixed = |step| CaptureTimestep::Fixed {
+			step,
+			drive_time: false,
+		};
+		assert_eq!(CaptureTimestep::Variable.frame_duration(delta), delta);
+		assert_eq!(
+			fixed(Duration::from_millis(20)).frame_duration(delta),
+			Duration::from_millis(20)
+		);
+		assert_eq!(fixed(Duration::ZERO).frame_duration(delta), delta);
+	}
 }
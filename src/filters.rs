@@ -0,0 +1,610 @@
+//! Built in [`FrameFilter`]s, which can be added to a [`FilterChain`] on a capture request or a
+//! recorder
+
+use std::time::Duration;
+
+use bevy_asset::Assets;
+use bevy_ecs::query::With;
+use bevy_ecs::system::Query;
+use bevy_render::camera::OrthographicProjection;
+use bevy_render::texture::Image;
+use bevy_render::view::RenderLayers;
+use bevy_transform::components::Transform;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use wgpu::TextureFormat;
+
+use crate::data::{
+	ActiveRecorder, CaptureRegion, FilterChain, FilterContext, Follow, FrameFilter, OutputScale,
+	Recorder, RecorderID, Redaction, RedactionStyle, RgbaFrame,
+};
+use crate::image_utils::{frame_data_to_rgba_image, image_to_rgba_frame, rgba_frame_to_image};
+#[cfg(feature = "text-overlay")]
+use crate::overlay::PreparedText;
+use crate::overlay::PreparedWatermark;
+
+/// Cut out a rectangle of the frame. The rectangle is clamped to the edges of the frame
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Crop {
+	/// The left edge of the rectangle, in pixels
+	pub x: u32,
+	/// The top edge of the rectangle, in pixels
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl FrameFilter for Crop {
+	fn apply(&self, frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		let mut image = rgba_frame_to_image(frame);
+		let x = self.x.min(image.width().saturating_sub(1));
+		let y = self.y.min(image.height().saturating_sub(1));
+		let width = self.width.clamp(1, image.width() - x);
+		let height = self.height.clamp(1, image.height() - y);
+		image_to_rgba_frame(imageops::crop(&mut image, x, y, width, height).to_image())
+	}
+}
+
+/// Scale the frame to an exact size, ignoring its aspect ratio
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Resize {
+	pub width: u32,
+	pub height: u32,
+	/// The sampling filter used to scale the frame
+	pub filter: FilterType,
+}
+
+impl FrameFilter for Resize {
+	fn apply(&self, frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		if frame.width == self.width && frame.height == self.height {
+			return frame;
+		}
+
+		let image = rgba_frame_to_image(frame);
+		image_to_rgba_frame(imageops::resize(
+			&image,
+			self.width.max(1),
+			self.height.max(1),
+			self.filter,
+		))
+	}
+}
+
+/// Convert the frame to shades of grey, keeping its alpha channel
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Default)]
+pub struct Grayscale;
+
+impl FrameFilter for Grayscale {
+	fn apply(&self, mut frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		for pixel in frame.pixels.chunks_exact_mut(4) {
+			let luma =
+				(pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000;
+			pixel[0] = luma as u8;
+			pixel[1] = luma as u8;
+			pixel[2] = luma as u8;
+		}
+		frame
+	}
+}
+
+/// Apply gamma correction to the colour channels of the frame. Values above 1.0 brighten the
+/// frame, and values below 1.0 darken it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Gamma(pub f32);
+
+impl FrameFilter for Gamma {
+	fn apply(&self, mut frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		if self.0 <= 0.0 || self.0 == 1.0 {
+			return frame;
+		}
+
+		let exponent = 1.0 / self.0;
+		let mut table = [0u8; 256];
+		for (value, entry) in table.iter_mut().enumerate() {
+			*entry = ((value as f32 / 255.0).powf(exponent) * 255.0).round() as u8;
+		}
+
+		for pixel in frame.pixels.chunks_exact_mut(4) {
+			pixel[0] = table[pixel[0] as usize];
+			pixel[1] = table[pixel[1] as usize];
+			pixel[2] = table[pixel[2] as usize];
+		}
+		frame
+	}
+}
+
+/// Fit the frame inside an exact size without changing its aspect ratio, filling the space
+/// around it with a solid colour
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Letterbox {
+	pub width: u32,
+	pub height: u32,
+	/// The RGBA colour of the bars around the frame
+	pub colour: [u8; 4],
+}
+
+impl FrameFilter for Letterbox {
+	fn apply(&self, frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		let (width, height) = (self.width.max(1), self.height.max(1));
+		if frame.width == width && frame.height == height {
+			return frame;
+		}
+
+		let scale = f64::min(
+			width as f64 / frame.width as f64,
+			height as f64 / frame.height as f64,
+		);
+		let scaled_width = ((frame.width as f64 * scale).round() as u32).clamp(1, width);
+		let scaled_height = ((frame.height as f64 * scale).round() as u32).clamp(1, height);
+
+		let image = rgba_frame_to_image(frame);
+		let scaled = imageops::resize(&image, scaled_width, scaled_height, FilterType::Triangle);
+
+		let mut output = RgbaImage::from_pixel(width, height, Rgba(self.colour));
+		imageops::replace(
+			&mut output,
+			&scaled,
+			((width - scaled_width) / 2) as i64,
+			((height - scaled_height) / 2) as i64,
+		);
+		image_to_rgba_frame(output)
+	}
+}
+
+impl FrameFilter for OutputScale {
+	fn apply(&self, frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		match *self {
+			OutputScale::Integer(factor) => upscale_nearest(frame, factor),
+			OutputScale::Fit { width, height } => {
+				let scale = f64::min(
+					width.max(1) as f64 / frame.width as f64,
+					height.max(1) as f64 / frame.height as f64,
+				);
+				if scale >= 1.0 {
+					return frame;
+				}
+
+				let scaled_width = ((frame.width as f64 * scale).round() as u32).max(1);
+				let scaled_height = ((frame.height as f64 * scale).round() as u32).max(1);
+				let image = rgba_frame_to_image(frame);
+				image_to_rgba_frame(imageops::resize(
+					&image,
+					scaled_width,
+					scaled_height,
+					FilterType::Lanczos3,
+				))
+			}
+		}
+	}
+}
+
+/// Repeat every pixel of `frame` `factor` times horizontally and vertically. The frame is left
+/// unscaled if the scaled size wouldn't fit in a `u32`
+fn upscale_nearest(frame: RgbaFrame, factor: u32) -> RgbaFrame {
+	if factor <= 1 || frame.width == 0 {
+		return frame;
+	}
+
+	let (width, height) = match (
+		frame.width.checked_mul(factor),
+		frame.height.checked_mul(factor),
+	) {
+		(Some(width), Some(height)) => (width, height),
+		_ => {
+			log::warn!(
+				"Scaling a {}x{} frame by {} is too large, capturing it unscaled",
+				frame.width,
+				frame.height,
+				factor
+			);
+			return frame;
+		}
+	};
+	let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+	for row in frame.pixels.chunks_exact(frame.width as usize * 4) {
+		let row_start = pixels.len();
+		for pixel in row.chunks_exact(4) {
+			for _ in 0..factor {
+				pixels.extend_from_slice(pixel);
+			}
+		}
+		let row_end = pixels.len();
+		for _ in 1..factor {
+			pixels.extend_from_within(row_start..row_end);
+		}
+	}
+
+	RgbaFrame {
+		width,
+		height,
+		pixels,
+	}
+}
+
+/// The view of every tracking camera, used to convert world space regions to pixels and to
+/// decide which entities a recorder can see
+pub(crate) type TrackerViews<'w, 's> = Query<
+	'w,
+	's,
+	(
+		&'static Transform,
+		&'static OrthographicProjection,
+		Option<&'static Follow>,
+		Option<&'static RenderLayers>,
+	),
+	With<Recorder>,
+>;
+
+/// Build the filter that crops frames of `size` from `recorder` to `region`. When the recorder
+/// follows an entity, the crop is applied to the box around the entity instead of the whole
+/// frame. Returns `None` when there is nothing to crop, or the region can't be captured
+fn crop_filter(
+	region: Option<CaptureRegion>,
+	recorder: &ActiveRecorder,
+	trackers: &TrackerViews,
+	size: (u32, u32),
+) -> Option<Crop> {
+	let region = region?;
+	let (transform, projection, follow, _) = match trackers.get(recorder.tracker) {
+		Ok(view) => view,
+		Err(_) => {
+			log::error!(
+				"Could not find the tracking camera for a recorder, capturing the whole frame"
+			);
+			return None;
+		}
+	};
+
+	// The followed box moves from frame to frame, so a world space region can't be turned into a
+	// single crop of it
+	let size = match (follow, region) {
+		(Some(_), CaptureRegion::World { .. }) => {
+			log::warn!(
+				"World space capture regions can't be used by a recorder that follows an entity, capturing the whole box"
+			);
+			return None;
+		}
+		(Some(follow), CaptureRegion::Pixels { .. }) => (
+			follow.width.clamp(1, size.0.max(1)),
+			follow.height.clamp(1, size.1.max(1)),
+		),
+		(None, _) => size,
+	};
+
+	let crop = pixel_crop(region.in_pixels(transform, projection, size));
+	if crop.is_none() {
+		log::warn!("Capture region is outside of the frame, capturing the whole frame");
+	}
+	crop
+}
+
+/// Cover up every redacted area of a frame, before it is cropped or filtered
+fn redact(frame: RgbaFrame, redactions: &[Redaction]) -> RgbaFrame {
+	if redactions.is_empty() {
+		return frame;
+	}
+
+	let mut image = rgba_frame_to_image(frame);
+	for redaction in redactions {
+		let Crop {
+			x,
+			y,
+			width,
+			height,
+		} = match pixel_crop(Some(redaction.region)) {
+			Some(crop) => crop,
+			None => continue,
+		};
+		if x >= image.width() || y >= image.height() {
+			continue;
+		}
+		let width = width.min(image.width() - x);
+		let height = height.min(image.height() - y);
+
+		match redaction.style {
+			RedactionStyle::Fill(colour) => {
+				let fill = RgbaImage::from_pixel(width, height, Rgba(colour));
+				imageops::replace(&mut image, &fill, x as i64, y as i64);
+			}
+			RedactionStyle::Blur(sigma) => {
+				let area = imageops::crop_imm(&image, x, y, width, height).to_image();
+				let blurred = imageops::blur(&area, sigma.max(0.0));
+				imageops::replace(&mut image, &blurred, x as i64, y as i64);
+			}
+		}
+	}
+	image_to_rgba_frame(image)
+}
+
+/// Build the filter that crops a frame to a region that has already been converted to pixels
+fn pixel_crop(region: Option<CaptureRegion>) -> Option<Crop> {
+	match region? {
+		CaptureRegion::Pixels {
+			x,
+			y,
+			width,
+			height,
+		} => Some(Crop {
+			x,
+			y,
+			width,
+			height,
+		}),
+		CaptureRegion::World { .. } => None,
+	}
+}
+
+/// Collect the filters for a single frame capture of `size`: the crop, the recorder's
+/// filters, the request's filters, the output scale, and then any overlays
+#[cfg(any(
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff"
+))]
+pub(crate) fn frame_filters<T>(
+	event: &crate::data::CaptureFrame<T>,
+	recorder: &ActiveRecorder,
+	watermark: Option<&crate::data::Watermark>,
+	images: &Assets<Image>,
+	trackers: &TrackerViews,
+	size: (u32, u32),
+) -> FilterChain {
+	let mut filters = FilterChain::new();
+	if let Some(crop) = crop_filter(event.crop, recorder, trackers, size) {
+		filters.push(crop);
+	}
+	filters.extend(&recorder.filters);
+	filters.extend(&event.filters);
+	if let Some(scale) = event.scale {
+		filters.push(scale);
+	}
+	if let Some(watermark) =
+		watermark.and_then(|watermark| PreparedWatermark::new(watermark, images))
+	{
+		filters.push(watermark);
+	}
+	#[cfg(feature = "text-overlay")]
+	if let Some(text) = &event.text {
+		filters.push(PreparedText::new(text));
+	}
+	filters
+}
+
+/// Collect the filters for a recording of `size`: the crop, the recorder's filters, the
+/// request's filters, the output scale, and then any overlays
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "sequence",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg",
+	feature = "pipe"
+))]
+pub(crate) fn recording_filters<T>(
+	event: &crate::data::CaptureRecording<T>,
+	recorder: &ActiveRecorder,
+	images: &Assets<Image>,
+	trackers: &TrackerViews,
+	size: (u32, u32),
+) -> FilterChain {
+	let mut filters = FilterChain::new();
+	if let Some(crop) = crop_filter(event.crop, recorder, trackers, size) {
+		filters.push(crop);
+	}
+	filters.extend(&recorder.filters);
+	filters.extend(&event.filters);
+	if let Some(scale) = event.scale {
+		filters.push(scale);
+	}
+	if let Some(watermark) = event
+		.watermark
+		.as_ref()
+		.and_then(|watermark| PreparedWatermark::new(watermark, images))
+	{
+		filters.push(watermark);
+	}
+	#[cfg(feature = "text-overlay")]
+	if let Some(text) = &event.text {
+		filters.push(PreparedText::new(text));
+	}
+	filters
+}
+
+/// Describe the stored frame at `index` for a single frame capture
+#[cfg(any(
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff"
+))]
+pub(crate) fn single_frame_context(
+	recorder: &ActiveRecorder,
+	tracking_id: RecorderID,
+	index: usize,
+) -> FilterContext {
+	FilterContext {
+		recorder: tracking_id,
+		index,
+		ago: recorder
+			.frames
+			.iter()
+			.skip(index + 1)
+			.fold(Duration::ZERO, |total, frame| total + frame.frame_time),
+	}
+}
+
+/// Convert a single frame to RGBA, cover up its redactions, crop it to the region that was
+/// recorded with it, and run it through `filters`. Returns `None` if a filter produced a frame
+/// with the wrong number of pixels
+#[cfg(any(
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff"
+))]
+pub(crate) fn filter_frame(
+	width: u32,
+	height: u32,
+	frame: &crate::data::TextureFrame,
+	format: TextureFormat,
+	filters: &FilterChain,
+	context: &FilterContext,
+) -> Option<RgbaImage> {
+	let image = frame_data_to_rgba_image(width, height, &frame.texture, format);
+	let crop = pixel_crop(frame.region);
+	if crop.is_none() && frame.redactions.is_empty() && filters.is_empty() {
+		return Some(image);
+	}
+
+	let mut filtered = redact(image_to_rgba_frame(image), &frame.redactions);
+	if let Some(crop) = crop {
+		filtered = crop.apply(filtered, context);
+	}
+	let frame = filters.apply(filtered, context)?;
+	Some(rgba_frame_to_image(frame))
+}
+
+/// Cover up the redactions in every frame of a recording, crop it to the region that was
+/// recorded with it, and run `filters` over it. Filtered frames are converted to RGBA and may
+/// have been resized, so the size and texture format of the output frames are returned
+/// alongside them. Frames that a filter breaks, or that don't match the size of the first
+/// filtered frame, are dropped
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "sequence",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg",
+	feature = "pipe"
+))]
+pub(crate) fn filter_frames(
+	width: u32,
+	height: u32,
+	frames: std::collections::VecDeque<crate::data::TextureFrame>,
+	format: TextureFormat,
+	filters: &FilterChain,
+	tracking_id: RecorderID,
+) -> (
+	std::collections::VecDeque<crate::data::TextureFrame>,
+	u32,
+	u32,
+	TextureFormat,
+) {
+	if filters.is_empty()
+		&& frames
+			.iter()
+			.all(|frame| frame.region.is_none() && frame.redactions.is_empty())
+	{
+		return (frames, width, height, format);
+	}
+
+	// Each frame stopped being shown when the frames after it started
+	let mut ago = frames
+		.iter()
+		.fold(Duration::ZERO, |total, frame| total + frame.frame_time);
+	let mut size = None;
+	let mut output = std::collections::VecDeque::with_capacity(frames.len());
+
+	for (index, frame) in frames.into_iter().enumerate() {
+		ago = ago.saturating_sub(frame.frame_time);
+		let context = FilterContext {
+			recorder: tracking_id,
+			index,
+			ago,
+		};
+
+		let image = frame_data_to_rgba_image(width, height, &frame.texture, format);
+		let mut filtered = redact(image_to_rgba_frame(image), &frame.redactions);
+		if let Some(crop) = pixel_crop(frame.region) {
+			filtered = crop.apply(filtered, &context);
+		}
+		let filtered = match filters.apply(filtered, &context) {
+			Some(filtered) => filtered,
+			None => continue,
+		};
+		let frame_size = (filtered.width, filtered.height);
+		if *size.get_or_insert(frame_size) != frame_size {
+			log::error!(
+				"A frame filter changed the size of frame {}, skipping it",
+				index
+			);
+			continue;
+		}
+
+		output.push_back(crate::data::TextureFrame {
+			texture: std::sync::Arc::new(filtered.pixels),
+			frame_time: frame.frame_time,
+			region: None,
+			redactions: Vec::new(),
+		});
+	}
+
+	let (width, height) = size.unwrap_or((width, height));
+	(output, width, height, TextureFormat::Rgba8UnormSrgb)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A frame of `width` by `height` pixels, where every byte of a pixel holds its index
+	fn numbered_frame(width: u32, height: u32) -> RgbaFrame {
+		RgbaFrame {
+			width,
+			height,
+			pixels: (0..width * height)
+				.flat_map(|index| [index as u8; 4])
+				.collect(),
+		}
+	}
+
+	fn pixel_indices(frame: &RgbaFrame) -> Vec<u8> {
+		frame.pixels.chunks_exact(4).map(|pixel| pixel[0]).collect()
+	}
+
+	#[test]
+	fn upscale_nearest_repeats_every_pixel() {
+		let scaled = upscale_nearest(numbered_frame(2, 2), 3);
+		assert_eq!((scaled.width, scaled.height), (6, 6));
+		assert_eq!(
+			pixel_indices(&scaled),
+			[
+				[0, 0, 0, 1, 1, 1],
+				[0, 0, 0, 1, 1, 1],
+				[0, 0, 0, 1, 1, 1],
+				[2, 2, 2, 3, 3, 3],
+				[2, 2, 2, 3, 3, 3],
+				[2, 2, 2, 3, 3, 3],
+			]
+			.concat()
+		);
+	}
+
+	#[test]
+	fn upscale_nearest_leaves_the_frame_alone_when_it_cant_scale() {
+		for (frame, factor) in [
+			(numbered_frame(3, 2), 1),
+			(numbered_frame(3, 2), 0),
+			(numbered_frame(3, 2), u32::MAX),
+			(numbered_frame(0, 0), 4),
+		] {
+			let (size, pixels) = ((frame.width, frame.height), frame.pixels.clone());
+			let scaled = upscale_nearest(frame, factor);
+			assert_eq!((scaled.width, scaled.height), size);
+			assert_eq!(scaled.pixels, pixels);
+		}
+	}
+}
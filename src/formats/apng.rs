@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::to_rgba;
+use crate::output::save_bytes;
+use crate::timing::{millisecond_delays, resample};
+
+/// Encode the frame buffer as an animated PNG. Unlike GIF, every frame keeps its full colour
+/// depth and alpha channel
+pub struct RecordApng;
+pub type CaptureApngRecording = CaptureRecording<RecordApng>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveApngRecording(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveApngRecording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+/// APNG frame delays are stored as a fraction of a second. Using a denominator of 1000
+/// lets us write the frame time directly in milliseconds
+const DELAY_DENOMINATOR: u16 = 1000;
+
+/// Encode a set of frames into the bytes of an infinitely looping APNG file
+pub fn encode_apng(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> Result<Vec<u8>, EncodingError> {
+	let mut buffer = Vec::new();
+
+	let mut encoder = Encoder::new(&mut buffer, width, height);
+	encoder.set_color(ColorType::Rgba);
+	encoder.set_depth(BitDepth::Eight);
+	// 0 plays = loop forever
+	encoder.set_animated(frames.len() as u32, 0)?;
+
+	let delays = millisecond_delays(frames.iter().map(|frame| frame.frame_time));
+	let mut writer = encoder.write_header()?;
+	for (frame, delay) in frames.into_iter().zip(delays) {
+		writer.set_frame_delay(delay.min(u16::MAX as u64) as u16, DELAY_DENOMINATOR)?;
+		writer.write_image_data(&to_rgba(&frame.texture, format))?;
+	}
+	writer.finish()?;
+
+	Ok(buffer)
+}
+
+pub fn capture_apng_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureApngRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let bytes = match encode_apng(width, height, frames, target_format) {
+					Ok(bytes) => bytes,
+					Err(e) => {
+						log::error!("Failed to encode APNG: {}", e);
+						return;
+					}
+				};
+
+				save_bytes(event.path, "png", bytes);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveApngRecording(task));
+		}
+	}
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::Duration;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use rav1e::prelude::*;
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::{rgba_to_yuv420, to_rgba};
+use crate::output::save_bytes;
+use crate::timing::{average_frame_rate, resample};
+
+/// Encode the frame buffer as an AV1 video in an IVF container
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct RecordAv1 {
+	/// The encoder speed preset, from 0 (slowest, smallest output) to 10 (fastest). Values
+	/// outside of this range will be clamped
+	pub speed: u8,
+	/// The target bitrate, in kilobits per second. A bitrate of 0 will encode with a constant
+	/// quality instead of targeting a bitrate
+	pub bitrate: u32,
+}
+
+impl Default for RecordAv1 {
+	fn default() -> Self {
+		Self {
+			speed: 6,
+			bitrate: 0,
+		}
+	}
+}
+
+pub type CaptureAv1Recording = CaptureRecording<RecordAv1>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveAv1Recording(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveAv1Recording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+/// IVF timestamps are written in milliseconds, so that each frame can keep its own duration
+const IVF_TIMEBASE: u32 = 1000;
+
+fn ivf_header(width: u32, height: u32, frame_count: u32) -> Vec<u8> {
+	let mut header = Vec::with_capacity(32);
+	header.extend_from_slice(b"DKIF");
+	header.extend_from_slice(&0u16.to_le_bytes());
+	header.extend_from_slice(&32u16.to_le_bytes());
+	header.extend_from_slice(b"AV01");
+	header.extend_from_slice(&(width as u16).to_le_bytes());
+	header.extend_from_slice(&(height as u16).to_le_bytes());
+	header.extend_from_slice(&IVF_TIMEBASE.to_le_bytes());
+	header.extend_from_slice(&1u32.to_le_bytes());
+	header.extend_from_slice(&frame_count.to_le_bytes());
+	header.extend_from_slice(&0u32.to_le_bytes());
+	header
+}
+
+/// Convert the time since the start of the recording into IVF timestamp ticks. Frames are
+/// timed from their exact start time, so rounding errors don't build up over a recording
+fn timestamp_ticks(elapsed: Duration) -> u64 {
+	(elapsed.as_nanos() * IVF_TIMEBASE as u128 / 1_000_000_000) as u64
+}
+
+/// Encode a set of frames into the bytes of an AV1 IVF file
+pub fn encode_av1(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+	settings: RecordAv1,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+	let (rate_numerator, rate_denominator) = average_frame_rate(&frames);
+	let config = Config::new().with_encoder_config(EncoderConfig {
+		width: width as usize,
+		height: height as usize,
+		time_base: Rational::new(rate_denominator, rate_numerator),
+		speed_settings: SpeedSettings::from_preset(settings.speed.min(10)),
+		bitrate: (settings.bitrate as i64 * 1000).min(i32::MAX as i64) as i32,
+		..Default::default()
+	});
+	let mut context: Context<u8> = config.new_context()?;
+
+	let chroma_width = (width as usize).div_ceil(2);
+	let mut timestamps = Vec::with_capacity(frames.len());
+	let mut elapsed = Duration::ZERO;
+	for frame in frames {
+		timestamps.push(timestamp_ticks(elapsed));
+		elapsed += frame.frame_time;
+
+		let yuv = rgba_to_yuv420(width, height, &to_rgba(&frame.texture, format));
+		let mut input = context.new_frame();
+		input.planes[0].copy_from_raw_u8(&yuv.y, width as usize, 1);
+		input.planes[1].copy_from_raw_u8(&yuv.u, chroma_width, 1);
+		input.planes[2].copy_from_raw_u8(&yuv.v, chroma_width, 1);
+		context.send_frame(input)?;
+	}
+	context.flush();
+
+	let mut packets = Vec::new();
+	loop {
+		match context.receive_packet() {
+			Ok(packet) => {
+				let timestamp = timestamps
+					.get(packet.input_frameno as usize)
+					.copied()
+					.unwrap_or_else(|| timestamp_ticks(elapsed));
+				packets.push((timestamp, packet.data));
+			}
+			Err(EncoderStatus::Encoded) => continue,
+			Err(EncoderStatus::LimitReached) => break,
+			Err(e) => return Err(e.into()),
+		}
+	}
+
+	let mut output = ivf_header(width, height, packets.len() as u32);
+	for (timestamp, data) in packets {
+		output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		output.extend_from_slice(&timestamp.to_le_bytes());
+		output.extend_from_slice(&data);
+	}
+
+	Ok(output)
+}
+
+pub fn capture_av1_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureAv1Recording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let settings = event.capture_type;
+				let bytes = match encode_av1(width, height, frames, target_format, settings) {
+					Ok(bytes) => bytes,
+					Err(e) => {
+						log::error!("Failed to encode AV1 video: {}", e);
+						return;
+					}
+				};
+
+				save_bytes(event.path, "ivf", bytes);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveAv1Recording(task));
+		}
+	}
+}
@@ -1,9 +1,5 @@
-use std::borrow::{Borrow, Cow};
-use std::cell::RefCell;
+use std::borrow::Cow;
 use std::collections::VecDeque;
-use std::io::BufWriter;
-use std::rc::Rc;
-use std::sync::Arc;
 
 use bevy_asset::Assets;
 use bevy_ecs::component::Component;
@@ -13,13 +9,16 @@ use bevy_render::texture::Image;
 use bevy_tasks::{AsyncComputeTaskPool, Task};
 use color_quant::NeuQuant;
 use futures_lite::future;
-use gif::{Encoder, Frame, Repeat};
+use gif::{DisposalMethod, Encoder, EncodingError, Frame, Repeat};
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 use wgpu::TextureFormat;
 
 use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
-use crate::image_utils::{frame_data_to_rgba_image, to_rgba};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::to_rgba;
+use crate::output::save_bytes;
+use crate::timing::resample;
 
 pub struct RecordGif;
 pub type CaptureGifRecording = CaptureRecording<RecordGif>;
@@ -36,30 +35,66 @@ impl HasTaskStatus for SaveGifRecording {
 	}
 }
 
+/// The palette index used for fully transparent pixels, when a frame has any
+const TRANSPARENT_INDEX: u8 = 255;
+
 fn process_frame(
 	width: u16,
 	height: u16,
 	format: TextureFormat,
 	frame: TextureFrame,
 ) -> Frame<'static> {
-	let formatted = to_rgba(frame.texture, format);
-	let quant = NeuQuant::new(20, 256, formatted.as_slice());
+	let formatted = to_rgba(&frame.texture, format);
+	let has_transparency = formatted.chunks_exact(4).any(|pixel| pixel[3] == 0);
+
+	// GIF transparency is all or nothing, so fully transparent pixels are given their own
+	// palette entry and every other pixel is treated as opaque
+	let (quant, transparent) = if has_transparency {
+		let opaque: Vec<u8> = formatted
+			.chunks_exact(4)
+			.filter(|pixel| pixel[3] != 0)
+			.flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+			.collect();
+		let quant = if opaque.is_empty() {
+			None
+		} else {
+			Some(NeuQuant::new(20, TRANSPARENT_INDEX as usize, &opaque))
+		};
+		(quant, Some(TRANSPARENT_INDEX))
+	} else {
+		(Some(NeuQuant::new(20, 256, &formatted)), None)
+	};
+
 	let mut index_cache = fnv::FnvHashMap::default();
 	let pixels: Vec<u8> = formatted
 		.chunks(4)
-		.map(|pixel| {
-			*(index_cache
-				.entry(pixel)
-				.or_insert_with(|| quant.index_of(pixel) as u8))
+		.map(|pixel| match (&quant, transparent) {
+			(_, Some(index)) if pixel[3] == 0 => index,
+			(Some(quant), _) => {
+				*(index_cache
+					.entry(pixel)
+					.or_insert_with(|| quant.index_of(&[pixel[0], pixel[1], pixel[2], 255]) as u8))
+			}
+			(None, _) => 0,
 		})
 		.collect();
 
+	let mut palette = quant.map(|quant| quant.color_map_rgb()).unwrap_or_default();
+	if let Some(index) = transparent {
+		palette.resize((index as usize + 1) * 3, 0);
+	}
+
 	let mut output = Frame::default();
 	// GIF delay is increments of 10ms in u16; duration gives millis in u128.
 	// Convert to GIF delay scale then do a capped conversion to u16
 	output.delay = (frame.frame_time.as_millis() / 10).min(u16::MAX as u128) as u16;
-	output.palette = Some(quant.color_map_rgb());
-	output.transparent = None;
+	output.palette = Some(palette);
+	output.transparent = transparent;
+	if transparent.is_some() {
+		// Clear each frame before drawing the next, otherwise the previous frame would show
+		// through the transparent pixels
+		output.dispose = DisposalMethod::Background;
+	}
 
 	output.left = 0;
 	output.top = 0;
@@ -99,58 +134,69 @@ pub fn quantize_frames(
 		.collect()
 }
 
+/// Encode a set of frames into the bytes of an infinitely looping GIF file
+pub fn encode_gif(
+	width: u16,
+	height: u16,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> Result<Vec<u8>, EncodingError> {
+	let mut buffer = Vec::new();
+	{
+		let mut encoder = Encoder::new(&mut buffer, width, height, &[])?;
+		encoder.set_repeat(Repeat::Infinite)?;
+		for frame in quantize_frames(width, height, frames, format) {
+			encoder.write_frame(&frame)?;
+		}
+	}
+	Ok(buffer)
+}
+
 pub fn capture_gif_recording(
 	mut commands: Commands,
 	mut recorders: ResMut<ActiveRecorders>,
 	mut events: ResMut<Events<CaptureRecording<RecordGif>>>,
 	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
 ) {
 	let thread_pool = AsyncComputeTaskPool::get();
 	'event_drain: for event in events.drain() {
-		if let Some(mut recorder) = recorders.get_mut(&event.tracking_id) {
-			let (target_size, target_format) = match images.get(&recorder.target_handle) {
-				Some(image) => (image.size(), image.texture_descriptor.format),
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
 				None => continue 'event_drain,
 			};
 
-			let frames = std::mem::replace(&mut recorder.frames, VecDeque::new());
-			let task = thread_pool.spawn(async move {
-				let target_size = target_size;
-				let target_format = target_format;
-				let frames = frames;
-
-				let out_buffer = std::fs::File::create("test.gif").unwrap();
-				let mut writer = BufWriter::new(out_buffer);
-
-				log::info!("Create encoder");
-
-				match gif::Encoder::new(writer, target_size.x as u16, target_size.y as u16, &[]) {
-					Ok(mut encoder) => {
-						log::info!("Got encoder");
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
 
-						encoder.set_repeat(Repeat::Infinite);
-						let frames = quantize_frames(
-							target_size.x as u16,
-							target_size.y as u16,
-							frames,
-							target_format,
-						);
-						log::info!("Done quantize");
-
-						for frame in frames {
-							encoder.write_frame(&frame).unwrap();
-						}
-
-						log::info!("Wrote thing");
-					}
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+
+				let bytes = match encode_gif(width as u16, height as u16, frames, target_format) {
+					Ok(bytes) => bytes,
 					Err(e) => {
-						log::error!("{}", e);
+						log::error!("Failed to encode GIF: {}", e);
+						return;
 					}
 				};
 
-				log::info!("DONE");
-
-				()
+				save_bytes(event.path, "gif", bytes);
 			});
 
 			#[cfg(target_arch = "wasm32")]
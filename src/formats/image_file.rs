@@ -0,0 +1,130 @@
+use std::io::Cursor;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_render::texture::TextureFormatPixelInfo;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use image::{ImageFormat, ImageResult, RgbaImage};
+
+use crate::data::{ActiveRecorders, CaptureFrame, HasTaskStatus, Watermark};
+use crate::filters::{filter_frame, frame_filters, single_frame_context, TrackerViews};
+use crate::output::save_bytes;
+
+/// A capture type that saves a single frame as an image file, using one of the
+/// formats supported by the `image` crate
+pub trait ImageFileFormat: Send + Sync + 'static {
+	/// The format that the frame should be encoded with
+	fn image_format(&self) -> ImageFormat;
+	/// A watermark that should be drawn on top of the frame before it is encoded
+	fn watermark(&self) -> Option<Watermark> {
+		None
+	}
+	/// Encode the filtered frame into the bytes of an image file. By default, the frame is
+	/// written with the `image` crate's encoder for [`ImageFileFormat::image_format`]
+	fn encode(&self, image: RgbaImage) -> ImageResult<Vec<u8>> {
+		let mut file_bytes = Cursor::new(Vec::with_capacity(image.len()));
+		image.write_to(&mut file_bytes, self.image_format())?;
+		Ok(file_bytes.into_inner())
+	}
+}
+
+/// Encode a single frame in any format that the `image` crate is able to write
+/// with an RGBA pixel layout. Enable the matching feature (e.g. `qoi`, `tga`,
+/// `bmp` or `tiff`) for the format that you want to use
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SaveImage(pub ImageFormat);
+
+impl ImageFileFormat for SaveImage {
+	fn image_format(&self) -> ImageFormat {
+		self.0
+	}
+}
+
+pub type SaveImageFile = CaptureFrame<SaveImage>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveFrameTask(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveFrameTask {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+pub fn save_single_frame<T: ImageFileFormat>(
+	mut commands: Commands,
+	mut events: ResMut<Events<CaptureFrame<T>>>,
+	recorders: ResMut<ActiveRecorders>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get(&event.tracking_id) {
+			let index = match recorder.frame_index(event.frame) {
+				Some(index) => index,
+				None => continue 'event_drain,
+			};
+			let frame = recorder.frames[index].clone();
+
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let filters = frame_filters(
+				&event,
+				recorder,
+				event.capture_type.watermark().as_ref(),
+				&images,
+				&trackers,
+				(width, height),
+			);
+			let context = single_frame_context(recorder, event.tracking_id, index);
+
+			let task = thread_pool.spawn(async move {
+				let frame = frame;
+				let format = target_format;
+				let image_format = event.capture_type.image_format();
+
+				let expected_size = width * height * format.pixel_size() as u32;
+				if expected_size != frame.texture.len() as u32 {
+					log::error!("Failed to assert that the data frame is correctly formatted");
+					return;
+				}
+
+				let image = match filter_frame(width, height, &frame, format, &filters, &context) {
+					Some(image) => image,
+					None => return,
+				};
+
+				let file_bytes = match event.capture_type.encode(image) {
+					Ok(bytes) => bytes,
+					Err(e) => {
+						log::error!("Failed to encode screenshot: {}", e);
+						return;
+					}
+				};
+
+				let extension = image_format.extensions_str().first().unwrap_or(&"img");
+				save_bytes(event.path, extension, file_bytes);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveFrameTask(task));
+		}
+	}
+}
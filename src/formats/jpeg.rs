@@ -0,0 +1,44 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, DynamicImage, ImageFormat, ImageResult, RgbaImage};
+
+pub use super::image_file::save_single_frame;
+use super::image_file::ImageFileFormat;
+#[cfg(not(target_arch = "wasm32"))]
+pub use super::image_file::SaveFrameTask;
+use crate::data::CaptureFrame;
+
+/// Encode a single frame as a JPEG image
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct SaveJpeg {
+	/// The encoding quality, from 1 (smallest file) to 100 (best quality). Values outside of
+	/// this range will be clamped
+	pub quality: u8,
+}
+
+impl Default for SaveJpeg {
+	fn default() -> Self {
+		Self { quality: 90 }
+	}
+}
+
+impl ImageFileFormat for SaveJpeg {
+	fn image_format(&self) -> ImageFormat {
+		ImageFormat::Jpeg
+	}
+
+	fn encode(&self, image: RgbaImage) -> ImageResult<Vec<u8>> {
+		// JPEG has no alpha channel, so the alpha of each pixel is discarded rather than
+		// being passed through to the encoder
+		let image = DynamicImage::ImageRgba8(image).into_rgb8();
+		let mut bytes = Vec::new();
+		JpegEncoder::new_with_quality(&mut bytes, self.quality.clamp(1, 100)).encode(
+			image.as_raw(),
+			image.width(),
+			image.height(),
+			ColorType::Rgb8,
+		)?;
+		Ok(bytes)
+	}
+}
+
+pub type SaveJpegFile = CaptureFrame<SaveJpeg>;
@@ -0,0 +1,375 @@
+use std::collections::VecDeque;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, DynamicImage, ImageResult};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::frame_data_to_rgba_image;
+use crate::output::save_bytes;
+use crate::riff::{push_chunk, push_list, riff_file};
+use crate::timing::resample;
+
+/// Encode the frame buffer as a Motion-JPEG video in an AVI container. Every frame is an
+/// independent JPEG image, which makes this much faster to encode than other video formats
+/// at the cost of a larger file.
+///
+/// AVI files have a constant frame rate, so the frame buffer is always resampled to
+/// [`CaptureRecording::frame_rate`], or to [`DEFAULT_FRAME_RATE`] if none is given
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct RecordMjpeg {
+	/// The encoding quality of each frame, from 1 (smallest file) to 100 (best quality).
+	/// Values outside of this range will be clamped
+	pub quality: u8,
+}
+
+impl Default for RecordMjpeg {
+	fn default() -> Self {
+		Self { quality: 85 }
+	}
+}
+
+/// The number of frames per second used when a recording doesn't ask for a frame rate
+pub const DEFAULT_FRAME_RATE: u32 = 30;
+
+pub type CaptureMjpegRecording = CaptureRecording<RecordMjpeg>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveMjpegRecording(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveMjpegRecording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+/// The index contains an entry for each frame
+const AVIF_HASINDEX: u32 = 0x10;
+/// Every MJPEG frame can be decoded on its own
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+fn encode_frame(
+	width: u32,
+	height: u32,
+	format: TextureFormat,
+	quality: u8,
+	frame: TextureFrame,
+) -> ImageResult<Vec<u8>> {
+	// JPEG has no alpha channel, so the alpha of each pixel is discarded
+	let image = DynamicImage::ImageRgba8(frame_data_to_rgba_image(
+		width,
+		height,
+		&frame.texture,
+		format,
+	))
+	.into_rgb8();
+
+	let mut bytes = Vec::new();
+	JpegEncoder::new_with_quality(&mut bytes, quality).encode(
+		image.as_raw(),
+		width,
+		height,
+		ColorType::Rgb8,
+	)?;
+	Ok(bytes)
+}
+
+#[cfg(feature = "parallel")]
+fn encode_frames(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+	quality: u8,
+) -> ImageResult<Vec<Vec<u8>>> {
+	frames
+		.into_par_iter()
+		.map(|frame| encode_frame(width, height, format, quality, frame))
+		.collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn encode_frames(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+	quality: u8,
+) -> ImageResult<Vec<Vec<u8>>> {
+	frames
+		.into_iter()
+		.map(|frame| encode_frame(width, height, format, quality, frame))
+		.collect()
+}
+
+fn main_header(
+	width: u32,
+	height: u32,
+	frame_rate: u32,
+	frame_count: u32,
+	max_frame: u32,
+) -> Vec<u8> {
+	let mut header = Vec::with_capacity(56);
+	for value in [
+		1_000_000 / frame_rate,
+		max_frame.saturating_mul(frame_rate),
+		0,
+		AVIF_HASINDEX,
+		frame_count,
+		0,
+		1,
+		max_frame,
+		width,
+		height,
+		0,
+		0,
+		0,
+		0,
+	] {
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+	header
+}
+
+fn stream_header(
+	width: u32,
+	height: u32,
+	frame_rate: u32,
+	frame_count: u32,
+	max_frame: u32,
+) -> Vec<u8> {
+	let mut header = Vec::with_capacity(56);
+	header.extend_from_slice(b"vids");
+	header.extend_from_slice(b"MJPG");
+	// Flags, priority & language
+	header.extend_from_slice(&0u32.to_le_bytes());
+	header.extend_from_slice(&0u16.to_le_bytes());
+	header.extend_from_slice(&0u16.to_le_bytes());
+	// Initial frames, scale, rate, start, length, buffer size, quality, sample size
+	for value in [0, 1, frame_rate, 0, frame_count, max_frame, u32::MAX, 0] {
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+	// The frame rectangle as left, top, right, bottom
+	for value in [0u16, 0, width as u16, height as u16] {
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+	header
+}
+
+fn stream_format(width: u32, height: u32) -> Vec<u8> {
+	let mut format = Vec::with_capacity(40);
+	format.extend_from_slice(&40u32.to_le_bytes());
+	format.extend_from_slice(&(width as i32).to_le_bytes());
+	format.extend_from_slice(&(height as i32).to_le_bytes());
+	// One plane with 24 bits per pixel
+	format.extend_from_slice(&1u16.to_le_bytes());
+	format.extend_from_slice(&24u16.to_le_bytes());
+	format.extend_from_slice(b"MJPG");
+	format.extend_from_slice(&(width * height * 3).to_le_bytes());
+	// Pixels per metre and palette information are unused
+	format.extend_from_slice(&[0; 16]);
+	format
+}
+
+/// Encode a set of frames into the bytes of a Motion-JPEG AVI file. Every frame is shown for
+/// `1 / frame_rate` seconds, so the frames should already have been resampled to that rate
+pub fn encode_mjpeg(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+	frame_rate: u32,
+	settings: RecordMjpeg,
+) -> ImageResult<Vec<u8>> {
+	let frame_rate = frame_rate.max(1);
+	let encoded = encode_frames(
+		width,
+		height,
+		frames,
+		format,
+		settings.quality.clamp(1, 100),
+	)?;
+
+	let mut movie = Vec::from(*b"movi");
+	let mut index = Vec::with_capacity(encoded.len() * 16);
+	let mut max_frame = 0;
+	for data in &encoded {
+		max_frame = max_frame.max(data.len() as u32);
+
+		// Index offsets are relative to the start of the "movi" list type
+		index.extend_from_slice(b"00dc");
+		index.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+		index.extend_from_slice(&(movie.len() as u32).to_le_bytes());
+		index.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+		push_chunk(&mut movie, b"00dc", data);
+	}
+
+	let frame_count = encoded.len() as u32;
+	let mut stream_list = Vec::new();
+	push_chunk(
+		&mut stream_list,
+		b"strh",
+		&stream_header(width, height, frame_rate, frame_count, max_frame),
+	);
+	push_chunk(&mut stream_list, b"strf", &stream_format(width, height));
+
+	let mut header_list = Vec::new();
+	push_chunk(
+		&mut header_list,
+		b"avih",
+		&main_header(width, height, frame_rate, frame_count, max_frame),
+	);
+	push_list(&mut header_list, b"strl", &stream_list);
+
+	let mut body = Vec::new();
+	push_list(&mut body, b"hdrl", &header_list);
+	push_list(&mut body, b"movi", &movie[4..]);
+	push_chunk(&mut body, b"idx1", &index);
+
+	Ok(riff_file(b"AVI ", &body))
+}
+
+pub fn capture_mjpeg_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureMjpegRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				// AVI needs a constant frame rate, so the buffer is resampled even when the
+				// recording didn't ask for a specific rate
+				let frame_rate = event
+					.frame_rate
+					.filter(|frame_rate| *frame_rate > 0)
+					.unwrap_or(DEFAULT_FRAME_RATE);
+				let frames = resample(frames, Some(frame_rate));
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let settings = event.capture_type;
+				let bytes = match encode_mjpeg(
+					width,
+					height,
+					frames,
+					target_format,
+					frame_rate,
+					settings,
+				) {
+					Ok(bytes) => bytes,
+					Err(e) => {
+						log::error!("Failed to encode MJPEG video: {}", e);
+						return;
+					}
+				};
+
+				save_bytes(event.path, "avi", bytes);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveMjpegRecording(task));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn read_u32(bytes: &[u8], at: usize) -> u32 {
+		u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+	}
+
+	#[test]
+	fn index_entries_point_at_their_frames() {
+		let frames: VecDeque<TextureFrame> = (0..3)
+			.map(|frame| {
+				let pixels = (0..8 * 8 * 4)
+					.map(|byte: usize| (byte * 37 + frame * 91) as u8)
+					.collect();
+				TextureFrame::zeroed(pixels)
+			})
+			.collect();
+		let bytes = encode_mjpeg(
+			8,
+			8,
+			frames,
+			TextureFormat::Rgba8UnormSrgb,
+			30,
+			RecordMjpeg::default(),
+		)
+		.unwrap();
+
+		assert_eq!(&bytes[..4], b"RIFF");
+		assert_eq!(read_u32(&bytes, 4) as usize, bytes.len() - 8);
+		assert_eq!(&bytes[8..12], b"AVI ");
+
+		let mut movie_start = None;
+		let mut index = None;
+		let mut position = 12;
+		while position < bytes.len() {
+			let size = read_u32(&bytes, position + 4) as usize;
+			let data = position + 8;
+			match &bytes[position..position + 4] {
+				b"LIST" if &bytes[data..data + 4] == b"movi" => movie_start = Some(data),
+				b"idx1" => index = Some(&bytes[data..data + size]),
+				_ => {}
+			}
+			position = data + size + size % 2;
+		}
+		assert_eq!(position, bytes.len());
+
+		let movie_start = movie_start.expect("No movi list");
+		let index = index.expect("No idx1 chunk");
+		assert_eq!(index.len(), 3 * 16);
+		for entry in index.chunks_exact(16) {
+			assert_eq!(&entry[..4], b"00dc");
+			assert_eq!(read_u32(entry, 4), AVIIF_KEYFRAME);
+			let chunk = movie_start + read_u32(entry, 8) as usize;
+			let size = read_u32(entry, 12);
+			assert_eq!(&bytes[chunk..chunk + 4], b"00dc");
+			assert_eq!(read_u32(&bytes, chunk + 4), size);
+			// Every frame is a complete JPEG image
+			assert_eq!(&bytes[chunk + 8..chunk + 10], &[0xFF, 0xD8]);
+		}
+	}
+}
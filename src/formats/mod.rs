@@ -1,4 +1,31 @@
+#[cfg(feature = "apng")]
+pub mod apng;
+#[cfg(feature = "video-av1")]
+pub mod av1;
 #[cfg(feature = "gif")]
 pub mod gif;
+#[cfg(any(
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff"
+))]
+pub mod image_file;
+#[cfg(feature = "jpeg")]
+pub mod jpeg;
+#[cfg(feature = "mjpeg")]
+pub mod mjpeg;
+#[cfg(all(feature = "pipe", not(target_arch = "wasm32")))]
+pub mod pipe;
 #[cfg(feature = "png")]
 pub mod png;
+#[cfg(all(feature = "sequence", not(target_arch = "wasm32")))]
+pub mod sequence;
+#[cfg(feature = "sprite-sheet")]
+pub mod sprite_sheet;
+#[cfg(feature = "webp")]
+pub mod webp;
+#[cfg(feature = "y4m")]
+pub mod y4m;
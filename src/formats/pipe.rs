@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::{EventWriter, Events};
+use bevy_ecs::system::{Commands, Query, Res, ResMut};
+use bevy_render::texture::Image;
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, RecorderID, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::to_rgba;
+use crate::timing::{average_frame_rate, resample};
+
+/// Stream the frame buffer to the standard input of an external program, such as `ffmpeg`.
+/// Frames are written one after another as raw, tightly packed RGBA pixels.
+///
+/// Each argument may contain the following placeholders, which are replaced before the
+/// program is started:
+///
+/// - `{width}` / `{height}`: The size of each frame, in pixels
+/// - `{pixel_format}`: The layout of each pixel; always `rgba`
+/// - `{frame_rate}`: The average frame rate of the buffer, as a fraction (e.g. `60/1`)
+/// - `{path}`: The `path` of the capture request, or an empty string when there isn't one
+///
+/// ## Example
+///
+/// ```rust
+/// # use bevy_capture_media::formats::pipe::RecordPipe;
+/// RecordPipe::new("ffmpeg", [
+/// 	"-y", "-f", "rawvideo", "-pixel_format", "{pixel_format}",
+/// 	"-video_size", "{width}x{height}", "-framerate", "{frame_rate}",
+/// 	"-i", "-", "{path}",
+/// ]);
+/// ```
+///
+/// When the program exits, a [`PipeRecordingFinished`] event is sent with its exit status
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Default)]
+pub struct RecordPipe {
+	/// The program to run
+	pub program: String,
+	/// The arguments to pass to the program
+	pub args: Vec<String>,
+}
+
+impl RecordPipe {
+	pub fn new<P, A, S>(program: P, args: A) -> Self
+	where
+		P: Into<String>,
+		A: IntoIterator<Item = S>,
+		S: Into<String>,
+	{
+		Self {
+			program: program.into(),
+			args: args.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
+pub type CapturePipeRecording = CaptureRecording<RecordPipe>;
+
+/// Sent when the external program for a pipe recording has exited, or could not be started
+#[derive(Debug)]
+pub struct PipeRecordingFinished {
+	/// The identifier for the camera tracker that the recording was captured from
+	pub tracking_id: RecorderID,
+	/// The exit status of the program
+	pub status: io::Result<ExitStatus>,
+}
+
+/// A pipe recording that is still running. Writing to the program and waiting for it to exit
+/// both block, so each recording runs on its own thread instead of a task pool
+#[derive(Component)]
+pub struct SavePipeRecording {
+	pub tracking_id: RecorderID,
+	/// The thread that is running the recording. Taken once the thread has finished
+	pub thread: Option<JoinHandle<io::Result<ExitStatus>>>,
+}
+
+/// The values that can be substituted into the arguments of a [`RecordPipe`]
+struct PipeTemplate {
+	width: u32,
+	height: u32,
+	frame_rate: (u64, u64),
+	path: Option<PathBuf>,
+}
+
+impl PipeTemplate {
+	fn apply(&self, argument: &str) -> String {
+		let path = self
+			.path
+			.as_ref()
+			.map(|path| path.display().to_string())
+			.unwrap_or_default();
+
+		argument
+			.replace("{width}", &self.width.to_string())
+			.replace("{height}", &self.height.to_string())
+			.replace("{pixel_format}", "rgba")
+			.replace(
+				"{frame_rate}",
+				&format!("{}/{}", self.frame_rate.0, self.frame_rate.1),
+			)
+			.replace("{path}", &path)
+	}
+}
+
+fn run_pipe(
+	settings: RecordPipe,
+	template: PipeTemplate,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> io::Result<ExitStatus> {
+	let mut child = Command::new(&settings.program)
+		.args(settings.args.iter().map(|arg| template.apply(arg)))
+		.stdin(Stdio::piped())
+		.spawn()?;
+
+	// Taking stdin means that it is closed once all of the frames are written, which tells
+	// the program that the stream has ended
+	if let Some(mut stdin) = child.stdin.take() {
+		for frame in frames {
+			if let Err(e) = stdin.write_all(&to_rgba(&frame.texture, format)) {
+				log::warn!("{} stopped accepting frames: {}", settings.program, e);
+				break;
+			}
+		}
+	}
+
+	child.wait()
+}
+
+pub fn capture_pipe_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CapturePipeRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let tracking_id = event.tracking_id;
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let thread = thread::spawn(move || {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let template = PipeTemplate {
+					width,
+					height,
+					frame_rate: average_frame_rate(&frames),
+					path: event.path,
+				};
+				run_pipe(event.capture_type, template, frames, target_format)
+			});
+
+			commands.spawn().insert(SavePipeRecording {
+				tracking_id,
+				thread: Some(thread),
+			});
+		}
+	}
+}
+
+pub fn report_pipe_recordings(
+	mut commands: Commands,
+	mut tasks: Query<(Entity, &mut SavePipeRecording)>,
+	mut finished: EventWriter<PipeRecordingFinished>,
+) {
+	for (entity, mut recording) in &mut tasks {
+		let finished_thread = match recording.thread.take() {
+			Some(thread) if thread.is_finished() => thread,
+			thread => {
+				recording.thread = thread;
+				continue;
+			}
+		};
+
+		let status = finished_thread
+			.join()
+			.unwrap_or_else(|_| Err(io::Error::other("the pipe recording thread panicked")));
+		if let Err(e) = &status {
+			log::error!("Failed to run pipe recording: {}", e);
+		}
+
+		finished.send(PipeRecordingFinished {
+			tracking_id: recording.tracking_id,
+			status,
+		});
+		commands.entity(entity).despawn();
+	}
+}
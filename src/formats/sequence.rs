@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use image::{ImageFormat, ImageResult};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::frame_data_to_rgba_image;
+use crate::timing::resample;
+
+/// Write every frame in the frame buffer to a directory as a numbered PNG
+/// (`frame_0001.png`, `frame_0002.png`, ...), alongside a `manifest.csv` that lists
+/// how long each frame was displayed for.
+///
+/// The `path` of the capture request is used as the output directory, and will be
+/// created if it does not exist. This capture type is not available on the web target
+pub struct RecordSequence;
+pub type CaptureSequenceRecording = CaptureRecording<RecordSequence>;
+
+#[derive(Component)]
+pub struct SaveSequenceRecording(pub Task<()>);
+
+impl HasTaskStatus for SaveSequenceRecording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.csv";
+
+fn frame_file_name(index: usize) -> String {
+	format!("frame_{:04}.png", index + 1)
+}
+
+fn write_manifest(directory: &Path, frames: &VecDeque<TextureFrame>) -> std::io::Result<()> {
+	let mut manifest = String::from("file,frame_time\n");
+	for (index, frame) in frames.iter().enumerate() {
+		let _ = writeln!(
+			manifest,
+			"{},{}",
+			frame_file_name(index),
+			frame.frame_time.as_secs_f64()
+		);
+	}
+
+	std::fs::write(directory.join(MANIFEST_FILE_NAME), manifest)
+}
+
+fn save_frame(
+	directory: &Path,
+	index: usize,
+	width: u32,
+	height: u32,
+	format: TextureFormat,
+	frame: TextureFrame,
+) -> ImageResult<()> {
+	frame_data_to_rgba_image(width, height, &frame.texture, format)
+		.save_with_format(directory.join(frame_file_name(index)), ImageFormat::Png)
+}
+
+#[cfg(feature = "parallel")]
+fn save_frames(
+	directory: &Path,
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> ImageResult<()> {
+	frames
+		.into_par_iter()
+		.enumerate()
+		.map(|(index, frame)| save_frame(directory, index, width, height, format, frame))
+		.collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn save_frames(
+	directory: &Path,
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> ImageResult<()> {
+	frames
+		.into_iter()
+		.enumerate()
+		.map(|(index, frame)| save_frame(directory, index, width, height, format, frame))
+		.collect()
+}
+
+pub fn capture_sequence_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureSequenceRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let directory = event.path.unwrap_or_else(|| {
+					PathBuf::from(
+						std::time::UNIX_EPOCH
+							.elapsed()
+							.unwrap()
+							.as_secs()
+							.to_string(),
+					)
+				});
+
+				if let Err(e) = std::fs::create_dir_all(&directory) {
+					log::error!(
+						"Failed to create sequence directory {}: {}",
+						directory.display(),
+						e
+					);
+					return;
+				}
+
+				if let Err(e) = write_manifest(&directory, &frames) {
+					log::error!("Failed to write sequence manifest: {}", e);
+					return;
+				}
+
+				if let Err(e) = save_frames(&directory, width, height, frames, target_format) {
+					log::error!("Failed to write sequence frame: {}", e);
+				}
+			});
+
+			commands.spawn().insert(SaveSequenceRecording(task));
+		}
+	}
+}
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use image::{imageops, ImageFormat, ImageResult, RgbaImage};
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::frame_data_to_rgba_image;
+use crate::output::{default_file_name, save_bytes};
+use crate::timing::{millisecond_delays, resample};
+
+/// Lay every frame of the frame buffer out in a grid on a single PNG sprite sheet. A JSON
+/// descriptor with the same name as the sheet lists the position of each frame on the sheet
+/// and how long it was shown for, in milliseconds
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Default)]
+pub struct RecordSpriteSheet {
+	/// The number of frames on each row of the sheet
+	///
+	/// ## `None`
+	///
+	/// Use enough columns to make the grid roughly square
+	pub columns: Option<u32>,
+	/// Remove fully transparent rows and columns from the edges of the frames. Every frame is
+	/// trimmed by the same amount so that the animation stays aligned, and the amount trimmed
+	/// from the top left is recorded in the descriptor
+	pub trim: bool,
+}
+
+pub type CaptureSpriteSheetRecording = CaptureRecording<RecordSpriteSheet>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveSpriteSheetRecording(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveSpriteSheetRecording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+/// A sprite sheet that has been laid out, ready to be saved
+pub struct SpriteSheet {
+	/// The encoded PNG image containing every frame
+	pub image: Vec<u8>,
+	/// The JSON descriptor for the sheet
+	pub descriptor: String,
+}
+
+/// Find the smallest rectangle that contains every pixel with any opacity in any of `frames`,
+/// as `(x, y, width, height)`. Returns `None` if every frame is fully transparent
+fn opaque_bounds(frames: &[RgbaImage]) -> Option<(u32, u32, u32, u32)> {
+	let mut bounds: Option<(u32, u32, u32, u32)> = None;
+	for frame in frames {
+		for (x, y, pixel) in frame.enumerate_pixels() {
+			if pixel[3] == 0 {
+				continue;
+			}
+			bounds = Some(match bounds {
+				Some((left, top, right, bottom)) => {
+					(left.min(x), top.min(y), right.max(x), bottom.max(y))
+				}
+				None => (x, y, x, y),
+			});
+		}
+	}
+
+	bounds.map(|(left, top, right, bottom)| (left, top, right - left + 1, bottom - top + 1))
+}
+
+/// Escape a string so that it can be written inside a JSON string literal
+fn escape_json(value: &str) -> String {
+	let mut escaped = String::with_capacity(value.len());
+	for character in value.chars() {
+		match character {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			character if character.is_control() => {
+				let _ = write!(escaped, "\\u{:04x}", character as u32);
+			}
+			character => escaped.push(character),
+		}
+	}
+	escaped
+}
+
+/// Lay out a set of frames on a sprite sheet, and describe it. `image_name` is the file name of
+/// the sheet, which is written into the descriptor
+pub fn encode_sprite_sheet(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+	settings: RecordSpriteSheet,
+	image_name: &str,
+) -> ImageResult<SpriteSheet> {
+	let durations = millisecond_delays(frames.iter().map(|frame| frame.frame_time));
+	let mut images: Vec<RgbaImage> = frames
+		.into_iter()
+		.map(|frame| frame_data_to_rgba_image(width, height, &frame.texture, format))
+		.collect();
+
+	let (trim_x, trim_y, frame_width, frame_height) = if settings.trim {
+		opaque_bounds(&images).unwrap_or((0, 0, width, height))
+	} else {
+		(0, 0, width, height)
+	};
+	if (frame_width, frame_height) != (width, height) {
+		images = images
+			.iter()
+			.map(|image| {
+				imageops::crop_imm(image, trim_x, trim_y, frame_width, frame_height).to_image()
+			})
+			.collect();
+	}
+
+	let count = images.len() as u32;
+	let columns = settings
+		.columns
+		.unwrap_or_else(|| (count as f64).sqrt().ceil() as u32)
+		.clamp(1, count.max(1));
+	let rows = count.div_ceil(columns).max(1);
+
+	let mut sheet = RgbaImage::new(frame_width * columns, frame_height * rows);
+	let mut descriptor = String::from("{\n");
+	let _ = writeln!(descriptor, "\t\"image\": \"{}\",", escape_json(image_name));
+	let _ = writeln!(descriptor, "\t\"frame_width\": {},", frame_width);
+	let _ = writeln!(descriptor, "\t\"frame_height\": {},", frame_height);
+	let _ = writeln!(descriptor, "\t\"columns\": {},", columns);
+	let _ = writeln!(descriptor, "\t\"rows\": {},", rows);
+	let _ = writeln!(
+		descriptor,
+		"\t\"trim\": {{ \"x\": {}, \"y\": {} }},",
+		trim_x, trim_y
+	);
+	descriptor.push_str("\t\"frames\": [\n");
+
+	for (index, (image, duration)) in images.iter().zip(durations).enumerate() {
+		let index = index as u32;
+		let x = (index % columns) * frame_width;
+		let y = (index / columns) * frame_height;
+		imageops::replace(&mut sheet, image, x as i64, y as i64);
+
+		let separator = if index + 1 < count { "," } else { "" };
+		let _ = writeln!(
+			descriptor,
+			"\t\t{{ \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \"duration\": {} }}{}",
+			x, y, frame_width, frame_height, duration, separator
+		);
+	}
+	descriptor.push_str("\t]\n}\n");
+
+	let mut image = Cursor::new(Vec::new());
+	sheet.write_to(&mut image, ImageFormat::Png)?;
+
+	Ok(SpriteSheet {
+		image: image.into_inner(),
+		descriptor,
+	})
+}
+
+pub fn capture_sprite_sheet_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureSpriteSheetRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+
+				// The descriptor is saved next to the sheet, so both need to share a name
+				let path = event.path.unwrap_or_else(|| default_file_name("png"));
+				let image_name = path
+					.file_name()
+					.map(|name| name.to_string_lossy().into_owned())
+					.unwrap_or_default();
+
+				let sheet = match encode_sprite_sheet(
+					width,
+					height,
+					frames,
+					target_format,
+					event.capture_type,
+					&image_name,
+				) {
+					Ok(sheet) => sheet,
+					Err(e) => {
+						log::error!("Failed to encode sprite sheet: {}", e);
+						return;
+					}
+				};
+
+				save_bytes(
+					Some(path.with_extension("json")),
+					"json",
+					sheet.descriptor.into_bytes(),
+				);
+				save_bytes(Some(path), "png", sheet.image);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveSpriteSheetRecording(task));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use image::Rgba;
+
+	use super::*;
+
+	fn frame_with_pixels(pixels: &[(u32, u32)]) -> RgbaImage {
+		let mut frame = RgbaImage::new(4, 4);
+		for &(x, y) in pixels {
+			frame.put_pixel(x, y, Rgba([255, 0, 0, 1]));
+		}
+		frame
+	}
+
+	#[test]
+	fn opaque_bounds_cover_every_frame() {
+		let frames = [frame_with_pixels(&[(1, 1)]), frame_with_pixels(&[(2, 3)])];
+		assert_eq!(opaque_bounds(&frames), Some((1, 1, 2, 3)));
+
+		let frames = [frame_with_pixels(&[(0, 0), (3, 3)])];
+		assert_eq!(opaque_bounds(&frames), Some((0, 0, 4, 4)));
+	}
+
+	#[test]
+	fn opaque_bounds_are_empty_for_transparent_frames() {
+		assert_eq!(opaque_bounds(&[frame_with_pixels(&[])]), None);
+		assert_eq!(opaque_bounds(&[]), None);
+	}
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use image::codecs::webp::WebPEncoder;
+use image::{ColorType, ImageResult};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::to_rgba;
+use crate::output::save_bytes;
+use crate::riff::{push_chunk, riff_file};
+use crate::timing::{millisecond_delays, resample};
+
+/// Encode the frame buffer as a lossless animated WebP
+pub struct RecordWebp;
+pub type CaptureWebpRecording = CaptureRecording<RecordWebp>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveWebpRecording(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveWebpRecording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+/// "RIFF", the file size, and "WEBP"
+const RIFF_HEADER_SIZE: usize = 12;
+const VP8X_FLAG_ANIMATION: u8 = 0b0000_0010;
+const VP8X_FLAG_ALPHA: u8 = 0b0001_0000;
+/// Overwrite the canvas with each frame instead of blending it with the previous one. Disposal
+/// is left as "none", since every frame covers the whole canvas anyway
+const ANMF_FLAG_NO_BLEND: u8 = 0b0000_0010;
+/// Frame durations are stored in milliseconds as a 24 bit integer
+const MAX_FRAME_DURATION: u64 = 0x00FF_FFFF;
+
+/// A single frame that has been encoded as a bare VP8L chunk, ready to be wrapped in an
+/// animation frame
+struct EncodedFrame {
+	chunk: Vec<u8>,
+	duration: Duration,
+}
+
+fn encode_frame(
+	width: u32,
+	height: u32,
+	format: TextureFormat,
+	frame: TextureFrame,
+) -> ImageResult<EncodedFrame> {
+	let pixels = to_rgba(&frame.texture, format);
+	let mut bytes = Vec::new();
+	WebPEncoder::new_lossless(&mut bytes).encode(&pixels, width, height, ColorType::Rgba8)?;
+
+	// The encoder always produces a simple file format WebP: a RIFF header followed by a single
+	// VP8L chunk. Dropping the header leaves us with the chunk itself
+	bytes.drain(..RIFF_HEADER_SIZE);
+
+	Ok(EncodedFrame {
+		chunk: bytes,
+		duration: frame.frame_time,
+	})
+}
+
+#[cfg(feature = "parallel")]
+fn encode_frames(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> ImageResult<Vec<EncodedFrame>> {
+	frames
+		.into_par_iter()
+		.map(|frame| encode_frame(width, height, format, frame))
+		.collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn encode_frames(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> ImageResult<Vec<EncodedFrame>> {
+	frames
+		.into_iter()
+		.map(|frame| encode_frame(width, height, format, frame))
+		.collect()
+}
+
+fn push_u24(buffer: &mut Vec<u8>, value: u32) {
+	buffer.extend_from_slice(&value.to_le_bytes()[..3]);
+}
+
+/// Encode a set of frames into the bytes of an infinitely looping, lossless animated WebP file
+pub fn encode_webp(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> ImageResult<Vec<u8>> {
+	let frames = encode_frames(width, height, frames, format)?;
+	let canvas_width = width.saturating_sub(1);
+	let canvas_height = height.saturating_sub(1);
+
+	let mut body = Vec::new();
+
+	let mut header = Vec::with_capacity(10);
+	header.push(VP8X_FLAG_ANIMATION | VP8X_FLAG_ALPHA);
+	header.extend_from_slice(&[0; 3]);
+	push_u24(&mut header, canvas_width);
+	push_u24(&mut header, canvas_height);
+	push_chunk(&mut body, b"VP8X", &header);
+
+	let mut animation = Vec::with_capacity(6);
+	// Transparent background, loop forever
+	animation.extend_from_slice(&0u32.to_le_bytes());
+	animation.extend_from_slice(&0u16.to_le_bytes());
+	push_chunk(&mut body, b"ANIM", &animation);
+
+	let delays = millisecond_delays(frames.iter().map(|frame| frame.duration));
+	for (frame, delay) in frames.into_iter().zip(delays) {
+		let mut animation_frame = Vec::with_capacity(16 + frame.chunk.len());
+		push_u24(&mut animation_frame, 0);
+		push_u24(&mut animation_frame, 0);
+		push_u24(&mut animation_frame, canvas_width);
+		push_u24(&mut animation_frame, canvas_height);
+		push_u24(&mut animation_frame, delay.min(MAX_FRAME_DURATION) as u32);
+		animation_frame.push(ANMF_FLAG_NO_BLEND);
+		animation_frame.extend_from_slice(&frame.chunk);
+		push_chunk(&mut body, b"ANMF", &animation_frame);
+	}
+
+	Ok(riff_file(b"WEBP", &body))
+}
+
+pub fn capture_webp_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureWebpRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let bytes = match encode_webp(width, height, frames, target_format) {
+					Ok(bytes) => bytes,
+					Err(e) => {
+						log::error!("Failed to encode WebP: {}", e);
+						return;
+					}
+				};
+
+				save_bytes(event.path, "webp", bytes);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveWebpRecording(task));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use image::codecs::webp::WebPDecoder;
+	use image::AnimationDecoder;
+
+	use super::*;
+
+	#[test]
+	fn animated_webp_round_trips() {
+		let pixels: Vec<Vec<u8>> = (0..4u8)
+			.map(|frame| (0..16u8).map(|byte| byte * 15 + frame).collect())
+			.collect();
+		let frames = pixels
+			.iter()
+			.map(|pixels| {
+				TextureFrame::with_duration(pixels.clone(), Duration::from_nanos(16_666_667))
+			})
+			.collect();
+
+		let bytes = encode_webp(2, 2, frames, TextureFormat::Rgba8UnormSrgb).unwrap();
+		let decoded = WebPDecoder::new(Cursor::new(bytes))
+			.unwrap()
+			.into_frames()
+			.collect_frames()
+			.unwrap();
+
+		assert_eq!(decoded.len(), pixels.len());
+		for (frame, pixels) in decoded.iter().zip(&pixels) {
+			assert_eq!(frame.buffer().as_raw(), pixels);
+		}
+		let delays: Vec<u32> = decoded
+			.iter()
+			.map(|frame| frame.delay().numer_denom_ms().0)
+			.collect();
+		assert_eq!(delays, [17, 16, 17, 17]);
+	}
+}
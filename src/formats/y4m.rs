@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+use bevy_asset::Assets;
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Commands, Res, ResMut};
+use bevy_render::texture::Image;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use wgpu::TextureFormat;
+
+use crate::data::{ActiveRecorders, CaptureRecording, HasTaskStatus, TextureFrame};
+use crate::filters::{filter_frames, recording_filters, TrackerViews};
+use crate::image_utils::{rgba_to_yuv420, to_rgba};
+use crate::output::save_bytes;
+use crate::timing::{average_frame_rate, resample};
+
+/// Write the frame buffer as an uncompressed YUV4MPEG2 stream, which can be read directly
+/// by most video editors and transcoders
+pub struct RecordY4m;
+pub type CaptureY4mRecording = CaptureRecording<RecordY4m>;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Component)]
+pub struct SaveY4mRecording(pub Task<()>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HasTaskStatus for SaveY4mRecording {
+	fn is_done(&mut self) -> bool {
+		let result = future::block_on(future::poll_once(&mut self.0));
+		result.is_some()
+	}
+}
+
+/// Encode a set of frames into the bytes of a YUV4MPEG2 stream with 4:2:0 chroma subsampling
+pub fn encode_y4m(
+	width: u32,
+	height: u32,
+	frames: VecDeque<TextureFrame>,
+	format: TextureFormat,
+) -> Vec<u8> {
+	// Y4M streams have a single, constant frame rate
+	let (rate_numerator, rate_denominator) = average_frame_rate(&frames);
+	let mut output = format!(
+		"YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n",
+		width, height, rate_numerator, rate_denominator
+	)
+	.into_bytes();
+
+	for frame in frames {
+		let yuv = rgba_to_yuv420(width, height, &to_rgba(&frame.texture, format));
+		output.extend_from_slice(b"FRAME\n");
+		output.extend_from_slice(&yuv.y);
+		output.extend_from_slice(&yuv.u);
+		output.extend_from_slice(&yuv.v);
+	}
+
+	output
+}
+
+pub fn capture_y4m_recording(
+	mut commands: Commands,
+	mut recorders: ResMut<ActiveRecorders>,
+	mut events: ResMut<Events<CaptureY4mRecording>>,
+	images: Res<Assets<Image>>,
+	trackers: TrackerViews,
+) {
+	let thread_pool = AsyncComputeTaskPool::get();
+	'event_drain: for event in events.drain() {
+		if let Some(recorder) = recorders.get_mut(&event.tracking_id) {
+			let (width, height, target_format) = match images.get(&recorder.target_handle) {
+				Some(image) => (
+					image.size().x as u32,
+					image.size().y as u32,
+					image.texture_descriptor.format,
+				),
+				None => continue 'event_drain,
+			};
+
+			let frames = recorder.take_frames(event.window, event.and_then);
+			if frames.is_empty() {
+				continue 'event_drain;
+			}
+
+			let filters = recording_filters(&event, recorder, &images, &trackers, (width, height));
+			let task = thread_pool.spawn(async move {
+				let frames = resample(frames, event.frame_rate);
+				let (frames, width, height, target_format) = filter_frames(
+					width,
+					height,
+					frames,
+					target_format,
+					&filters,
+					event.tracking_id,
+				);
+				let bytes = encode_y4m(width, height, frames, target_format);
+				save_bytes(event.path, "y4m", bytes);
+			});
+
+			#[cfg(target_arch = "wasm32")]
+			task.detach();
+			#[cfg(not(target_arch = "wasm32"))]
+			commands.spawn().insert(SaveY4mRecording(task));
+		}
+	}
+}
@@ -1,11 +1,15 @@
+use std::borrow::Cow;
+
 use bevy_render::texture::TextureFormatPixelInfo;
 use image::RgbaImage;
 use wgpu::TextureFormat;
 
+use crate::data::RgbaFrame;
+
 pub fn frame_data_to_rgba_image(
 	width: u32,
 	height: u32,
-	buffer: Vec<u8>,
+	buffer: &[u8],
 	format: TextureFormat,
 ) -> RgbaImage {
 	let pixels = buffer.chunks(format.pixel_size()).collect::<Vec<&[u8]>>();
@@ -29,20 +33,24 @@ pub fn frame_data_to_rgba_image(
 	})
 }
 
-pub fn to_rgba(buffer: Vec<u8>, format: TextureFormat) -> Vec<u8> {
+/// Convert a frame to tightly packed RGBA bytes. Frames that are already RGBA are borrowed
+/// rather than copied
+pub fn to_rgba(buffer: &[u8], format: TextureFormat) -> Cow<'_, [u8]> {
 	match format {
 		TextureFormat::Rgba8UnormSrgb
 		| TextureFormat::Rgba8Uint
 		| TextureFormat::Rgba8Sint
 		| TextureFormat::Rgba8Snorm
-		| TextureFormat::Rgba8Unorm => buffer,
-		TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => buffer
-			.chunks_exact(4)
-			.flat_map(|pixel| match pixel {
-				[b, g, r, a] => [*r, *g, *b, *a],
-				_ => panic!("Chunks didn't give us a 4 chunk"),
-			})
-			.collect(),
+		| TextureFormat::Rgba8Unorm => Cow::Borrowed(buffer),
+		TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => Cow::Owned(
+			buffer
+				.chunks_exact(4)
+				.flat_map(|pixel| match pixel {
+					[b, g, r, a] => [*r, *g, *b, *a],
+					_ => panic!("Chunks didn't give us a 4 chunk"),
+				})
+				.collect(),
+		),
 		_ => {
 			panic!("Unhandled texture format {:?}", format);
 		}
@@ -68,3 +76,107 @@ pub fn to_grouped_rgba(buffer: &[u8], format: TextureFormat) -> Vec<[u8; 4]> {
 		})
 		.collect()
 }
+
+/// View a filter frame as an image, without copying its pixels
+pub fn rgba_frame_to_image(frame: RgbaFrame) -> RgbaImage {
+	RgbaImage::from_raw(frame.width, frame.height, frame.pixels)
+		.expect("A filter produced a frame with the wrong number of pixels")
+}
+
+/// Convert an image into a filter frame, without copying its pixels
+pub fn image_to_rgba_frame(image: RgbaImage) -> RgbaFrame {
+	RgbaFrame {
+		width: image.width(),
+		height: image.height(),
+		pixels: image.into_raw(),
+	}
+}
+
+/// A frame of planar YUV 4:2:0 data. The chroma planes are half the width and height
+/// of the luma plane, rounded up
+#[cfg(any(feature = "y4m", feature = "video-av1"))]
+pub struct Yuv420 {
+	pub y: Vec<u8>,
+	pub u: Vec<u8>,
+	pub v: Vec<u8>,
+}
+
+/// Convert tightly packed RGBA pixels into limited range BT.601 YUV 4:2:0. Each chroma
+/// sample is the average of the (up to) 2x2 block of pixels that it covers
+#[cfg(any(feature = "y4m", feature = "video-av1"))]
+pub fn rgba_to_yuv420(width: u32, height: u32, rgba: &[u8]) -> Yuv420 {
+	let width = width as usize;
+	let height = height as usize;
+	let chroma_width = width.div_ceil(2);
+	let chroma_height = height.div_ceil(2);
+
+	let y = rgba
+		.chunks_exact(4)
+		.map(|pixel| {
+			let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+			(((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+		})
+		.collect();
+
+	let mut u = Vec::with_capacity(chroma_width * chroma_height);
+	let mut v = Vec::with_capacity(chroma_width * chroma_height);
+	for chroma_row in 0..chroma_height {
+		for chroma_column in 0..chroma_width {
+			let (mut r, mut g, mut b, mut count) = (0, 0, 0, 0);
+			for row in (chroma_row * 2)..(chroma_row * 2 + 2).min(height) {
+				for column in (chroma_column * 2)..(chroma_column * 2 + 2).min(width) {
+					let index = (row * width + column) * 4;
+					r += rgba[index] as i32;
+					g += rgba[index + 1] as i32;
+					b += rgba[index + 2] as i32;
+					count += 1;
+				}
+			}
+			let (r, g, b) = (r / count, g / count, b / count);
+
+			u.push((((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8);
+			v.push((((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8);
+		}
+	}
+
+	Yuv420 { y, u, v }
+}
+
+#[cfg(all(test, any(feature = "y4m", feature = "video-av1")))]
+mod tests {
+	use super::*;
+
+	const WHITE: [u8; 4] = [255, 255, 255, 255];
+	const BLACK: [u8; 4] = [0, 0, 0, 255];
+	const RED: [u8; 4] = [255, 0, 0, 255];
+
+	#[test]
+	fn rgba_to_yuv420_uses_limited_range_bt601() {
+		let yuv = rgba_to_yuv420(2, 2, &[WHITE, WHITE, WHITE, WHITE].concat());
+		assert_eq!((yuv.y, yuv.u, yuv.v), (vec![235; 4], vec![128], vec![128]));
+
+		let yuv = rgba_to_yuv420(2, 2, &[BLACK, BLACK, BLACK, BLACK].concat());
+		assert_eq!((yuv.y, yuv.u, yuv.v), (vec![16; 4], vec![128], vec![128]));
+
+		let yuv = rgba_to_yuv420(2, 2, &[RED, RED, RED, RED].concat());
+		assert_eq!((yuv.y, yuv.u, yuv.v), (vec![82; 4], vec![90], vec![240]));
+	}
+
+	#[test]
+	fn rgba_to_yuv420_rounds_chroma_planes_up() {
+		// The second chroma sample only covers the last column of pixels
+		let yuv = rgba_to_yuv420(3, 1, &[RED, RED, BLACK].concat());
+		assert_eq!(yuv.y, vec![82, 82, 16]);
+		assert_eq!(yuv.u, vec![90, 128]);
+		assert_eq!(yuv.v, vec![240, 128]);
+
+		let yuv = rgba_to_yuv420(3, 3, &WHITE.repeat(9));
+		assert_eq!((yuv.y.len(), yuv.u.len(), yuv.v.len()), (9, 4, 4));
+	}
+
+	#[test]
+	fn rgba_to_yuv420_handles_an_empty_frame() {
+		let yuv = rgba_to_yuv420(0, 0, &[]);
+		assert!(yuv.y.is_empty() && yuv.u.is_empty() && yuv.v.is_empty());
+	}
+}
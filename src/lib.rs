@@ -2,11 +2,93 @@
 
 #[allow(clippy::type_complexity)]
 pub mod data;
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff",
+	feature = "sequence",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg",
+	feature = "pipe"
+))]
+pub mod filters;
 pub mod formats;
-#[cfg(any(feature = "gif", feature = "png"))]
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff",
+	feature = "sequence",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg",
+	feature = "pipe"
+))]
 mod image_utils;
 mod management;
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg"
+))]
+mod output;
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "jpeg",
+	feature = "png",
+	feature = "qoi",
+	feature = "tga",
+	feature = "bmp",
+	feature = "tiff",
+	feature = "sequence",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg",
+	feature = "pipe"
+))]
+mod overlay;
 mod render;
+#[cfg(any(feature = "webp", feature = "mjpeg"))]
+mod riff;
+#[cfg(any(
+	feature = "apng",
+	feature = "gif",
+	feature = "sequence",
+	feature = "sprite-sheet",
+	feature = "webp",
+	feature = "y4m",
+	feature = "video-av1",
+	feature = "mjpeg",
+	feature = "pipe"
+))]
+mod timing;
 #[cfg(target_arch = "wasm32")]
 mod web_utils;
 
@@ -26,6 +108,8 @@ mod plugin {
 				.add_event::<data::StopTrackingCamera>()
 				.insert_resource(tracking_tracker)
 				.insert_resource(data_smuggler.clone())
+				.init_resource::<data::CaptureTimestep>()
+				.add_system_to_stage(CoreStage::First, management::drive_fixed_timestep)
 				.add_system_to_stage(CoreStage::First, management::clean_cameras)
 				.add_system_to_stage(CoreStage::First, management::move_camera_buffers)
 				.add_system_to_stage(CoreStage::PostUpdate, management::sync_tracking_cameras)
@@ -48,17 +132,155 @@ mod plugin {
 					management::clean_unmonitored_tasks::<formats::gif::SaveGifRecording>,
 				);
 			}
+			#[cfg(feature = "apng")]
+			{
+				app.add_event::<formats::apng::CaptureApngRecording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::apng::capture_apng_recording,
+					);
+
+				#[cfg(not(target_arch = "wasm32"))]
+				app.add_system_to_stage(
+					CoreStage::Last,
+					management::clean_unmonitored_tasks::<formats::apng::SaveApngRecording>,
+				);
+			}
+			#[cfg(feature = "jpeg")]
+			{
+				app.add_event::<formats::jpeg::SaveJpegFile>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::image_file::save_single_frame::<formats::jpeg::SaveJpeg>,
+					);
+			}
 			#[cfg(feature = "png")]
 			{
 				app.add_event::<formats::png::SavePngFile>()
-					.add_system_to_stage(CoreStage::PostUpdate, formats::png::save_single_frame);
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::image_file::save_single_frame::<formats::png::SavePng>,
+					);
+			}
+			#[cfg(any(feature = "qoi", feature = "tga", feature = "bmp", feature = "tiff"))]
+			{
+				app.add_event::<formats::image_file::SaveImageFile>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::image_file::save_single_frame::<formats::image_file::SaveImage>,
+					);
+			}
+			#[cfg(all(
+				not(target_arch = "wasm32"),
+				any(
+					feature = "jpeg",
+					feature = "png",
+					feature = "qoi",
+					feature = "tga",
+					feature = "bmp",
+					feature = "tiff"
+				)
+			))]
+			app.add_system_to_stage(
+				CoreStage::Last,
+				management::clean_unmonitored_tasks::<formats::image_file::SaveFrameTask>,
+			);
+			#[cfg(all(feature = "sequence", not(target_arch = "wasm32")))]
+			{
+				app.add_event::<formats::sequence::CaptureSequenceRecording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::sequence::capture_sequence_recording,
+					)
+					.add_system_to_stage(
+						CoreStage::Last,
+						management::clean_unmonitored_tasks::<
+							formats::sequence::SaveSequenceRecording,
+						>,
+					);
+			}
+			#[cfg(feature = "sprite-sheet")]
+			{
+				app.add_event::<formats::sprite_sheet::CaptureSpriteSheetRecording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::sprite_sheet::capture_sprite_sheet_recording,
+					);
+
+				#[cfg(not(target_arch = "wasm32"))]
+				app.add_system_to_stage(
+					CoreStage::Last,
+					management::clean_unmonitored_tasks::<
+						formats::sprite_sheet::SaveSpriteSheetRecording,
+					>,
+				);
+			}
+			#[cfg(feature = "webp")]
+			{
+				app.add_event::<formats::webp::CaptureWebpRecording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::webp::capture_webp_recording,
+					);
+
+				#[cfg(not(target_arch = "wasm32"))]
+				app.add_system_to_stage(
+					CoreStage::Last,
+					management::clean_unmonitored_tasks::<formats::webp::SaveWebpRecording>,
+				);
+			}
+			#[cfg(feature = "y4m")]
+			{
+				app.add_event::<formats::y4m::CaptureY4mRecording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::y4m::capture_y4m_recording,
+					);
 
 				#[cfg(not(target_arch = "wasm32"))]
 				app.add_system_to_stage(
 					CoreStage::Last,
-					management::clean_unmonitored_tasks::<formats::png::SaveFrameTask>,
+					management::clean_unmonitored_tasks::<formats::y4m::SaveY4mRecording>,
 				);
 			}
+			#[cfg(feature = "video-av1")]
+			{
+				app.add_event::<formats::av1::CaptureAv1Recording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::av1::capture_av1_recording,
+					);
+
+				#[cfg(not(target_arch = "wasm32"))]
+				app.add_system_to_stage(
+					CoreStage::Last,
+					management::clean_unmonitored_tasks::<formats::av1::SaveAv1Recording>,
+				);
+			}
+			#[cfg(feature = "mjpeg")]
+			{
+				app.add_event::<formats::mjpeg::CaptureMjpegRecording>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::mjpeg::capture_mjpeg_recording,
+					);
+
+				#[cfg(not(target_arch = "wasm32"))]
+				app.add_system_to_stage(
+					CoreStage::Last,
+					management::clean_unmonitored_tasks::<formats::mjpeg::SaveMjpegRecording>,
+				);
+			}
+			#[cfg(all(feature = "pipe", not(target_arch = "wasm32")))]
+			{
+				app.add_event::<formats::pipe::CapturePipeRecording>()
+					.add_event::<formats::pipe::PipeRecordingFinished>()
+					.add_system_to_stage(
+						CoreStage::PostUpdate,
+						formats::pipe::capture_pipe_recording,
+					)
+					.add_system_to_stage(CoreStage::Last, formats::pipe::report_pipe_recordings);
+			}
 
 			let render_app = app.get_sub_app_mut(RenderApp)
 				.expect("bevy_capture_media will not work without the render app. Either enable this sub app, or disable bevy_capture_media");
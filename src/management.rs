@@ -1,21 +1,27 @@
 use std::time::Duration;
 
 use bevy_asset::Assets;
-use bevy_core_pipeline::core_2d::Camera2dBundle;
+use bevy_core_pipeline::clear_color::ClearColorConfig;
+use bevy_core_pipeline::core_2d::{Camera2d, Camera2dBundle};
 use bevy_ecs::entity::Entity;
 use bevy_ecs::event::Events;
 use bevy_ecs::query::{With, Without};
-use bevy_ecs::system::{Commands, Query, Res, ResMut};
+use bevy_ecs::system::{Commands, Local, Query, Res, ResMut};
+use bevy_math::Vec2;
 use bevy_render::camera::{Camera, OrthographicProjection, RenderTarget};
+use bevy_render::color::Color;
 use bevy_render::texture::Image;
+use bevy_render::view::{ComputedVisibility, RenderLayers};
 use bevy_time::Time;
-use bevy_transform::components::Transform;
+use bevy_transform::components::{GlobalTransform, Transform};
 
-use crate::data::ProjectToImage;
+use crate::data::{world_to_pixel, ProjectToImage};
 use crate::data::{
-	ActiveRecorder, ActiveRecorders, HasTaskStatus, Recorder, RenderData, SharedDataSmuggler,
-	StartTrackingCamera, TextureFrame, Track,
+	ActiveRecorder, ActiveRecorders, CaptureRegion, CaptureTimestep, Follow, HasTaskStatus,
+	Recorder, RedactInCapture, Redaction, RenderData, SharedDataSmuggler, StartTrackingCamera,
+	TextureFrame, Track,
 };
+use crate::filters::TrackerViews;
 
 pub fn sync_tracking_cameras(
 	mut trackers: Query<(&mut Transform, &mut OrthographicProjection, &Track), With<Recorder>>,
@@ -56,12 +62,135 @@ pub fn clean_unmonitored_tasks<T: HasTaskStatus>(
 	}
 }
 
+pub fn drive_fixed_timestep(
+	timestep: Res<CaptureTimestep>,
+	mut time: ResMut<Time>,
+	mut virtual_time: Local<Option<Time>>,
+) {
+	match *timestep {
+		CaptureTimestep::Fixed {
+			step,
+			drive_time: true,
+		} if !step.is_zero() => {
+			// Keep our own copy of Time that only ever advances by `step`, and overwrite the
+			// real time resource with it after it has been updated for the frame
+			let virtual_time = virtual_time.get_or_insert_with(|| time.clone());
+			let last_update = match virtual_time.last_update().or_else(|| time.last_update()) {
+				Some(instant) => instant,
+				None => return,
+			};
+
+			virtual_time.update_with_instant(last_update + step);
+			*time = virtual_time.clone();
+		}
+		_ => {
+			*virtual_time = None;
+		}
+	}
+}
+
+/// Find the box that a following recorder should crop its next frame to, keeping the box
+/// inside the frame
+fn follow_region(
+	follow: &Follow,
+	transform: &Transform,
+	projection: &OrthographicProjection,
+	targets: &Query<&GlobalTransform>,
+) -> CaptureRegion {
+	let size = (
+		(projection.right - projection.left).max(0.0) as u32,
+		(projection.top - projection.bottom).max(0.0) as u32,
+	);
+	let (width, height) = (
+		follow.width.clamp(1, size.0.max(1)),
+		follow.height.clamp(1, size.1.max(1)),
+	);
+
+	let centre = targets
+		.get(follow.target)
+		.ok()
+		.and_then(|target| world_to_pixel(target.translation(), transform, projection, size))
+		.unwrap_or_else(|| Vec2::new(size.0 as f32, size.1 as f32) / 2.0);
+
+	let left = (centre.x - width as f32 / 2.0).round() as i64;
+	let top = (centre.y - height as f32 / 2.0).round() as i64;
+	CaptureRegion::Pixels {
+		x: left.clamp(0, size.0.saturating_sub(width) as i64) as u32,
+		y: top.clamp(0, size.1.saturating_sub(height) as i64) as u32,
+		width,
+		height,
+	}
+}
+
+/// Entities that should be covered up in captures, with the information needed to tell whether
+/// a tracking camera can see them
+type RedactedViews<'w, 's> = Query<
+	'w,
+	's,
+	(
+		&'static GlobalTransform,
+		&'static RedactInCapture,
+		Option<&'static ComputedVisibility>,
+		Option<&'static RenderLayers>,
+	),
+>;
+
+/// Find the area covered by every redacted entity that is visible to a tracking camera in its
+/// next frame. Hidden entities, and entities on layers that the camera doesn't render, can't
+/// appear in the frame and so aren't redacted
+fn redactions(
+	transform: &Transform,
+	projection: &OrthographicProjection,
+	layers: &RenderLayers,
+	redacted: &RedactedViews,
+) -> Vec<Redaction> {
+	let size = (
+		(projection.right - projection.left).max(0.0) as u32,
+		(projection.top - projection.bottom).max(0.0) as u32,
+	);
+
+	redacted
+		.iter()
+		.filter(|(_, _, visibility, entity_layers)| {
+			visibility.is_none_or(ComputedVisibility::is_visible)
+				&& entity_layers
+					.copied()
+					.unwrap_or_default()
+					.intersects(layers)
+		})
+		.filter_map(|(entity_transform, redact, _, _)| {
+			let matrix = entity_transform.compute_matrix();
+			let half = redact.size / 2.0;
+			let corners = [
+				Vec2::new(-half.x, -half.y),
+				Vec2::new(half.x, -half.y),
+				Vec2::new(-half.x, half.y),
+				Vec2::new(half.x, half.y),
+			]
+			.map(|corner| matrix.transform_point3(corner.extend(0.0)).truncate());
+
+			let min = corners.iter().copied().reduce(Vec2::min)?;
+			let max = corners.iter().copied().reduce(Vec2::max)?;
+			let region =
+				CaptureRegion::World { min, max }.in_pixels(transform, projection, size)?;
+			Some(Redaction {
+				region,
+				style: redact.style,
+			})
+		})
+		.collect()
+}
+
 pub fn move_camera_buffers(
 	time: Res<Time>,
+	timestep: Res<CaptureTimestep>,
 	mut smugglers: ResMut<SharedDataSmuggler>,
 	mut recorders: ResMut<ActiveRecorders>,
+	trackers: TrackerViews,
+	targets: Query<&GlobalTransform>,
+	redacted: RedactedViews,
 ) {
-	let dt = time.delta();
+	let dt = timestep.frame_duration(time.delta());
 	let mut smugglers = smugglers.lock().unwrap();
 	for (id, mut data) in smugglers.iter_mut() {
 		if data.last_frame.is_none() {
@@ -90,25 +219,55 @@ pub fn move_camera_buffers(
 				}
 			}
 
-			recorder.frames.push_back(TextureFrame::with_duration(
-				std::mem::replace(&mut data.last_frame, None)
-					.expect("A frame has disappeared in Lego City"),
-				dt,
-			));
+			let (region, redactions) = match trackers.get(recorder.tracker) {
+				Ok((transform, projection, follow, layers)) => (
+					follow.map(|follow| follow_region(follow, transform, projection, &targets)),
+					redactions(
+						transform,
+						projection,
+						&layers.copied().unwrap_or_default(),
+						&redacted,
+					),
+				),
+				Err(_) => (None, Vec::new()),
+			};
+
+			recorder.frames.push_back(TextureFrame {
+				region,
+				redactions,
+				..TextureFrame::with_duration(
+					std::mem::replace(&mut data.last_frame, None)
+						.expect("A frame has disappeared in Lego City"),
+					dt,
+				)
+			});
 		});
 	}
 }
 
+/// Cameras that can be tracked, with everything that a tracking camera copies from them
+type TrackedCameras<'w, 's> = Query<
+	'w,
+	's,
+	(
+		&'static Camera,
+		&'static Transform,
+		&'static OrthographicProjection,
+		Option<&'static RenderLayers>,
+		Option<&'static Camera2d>,
+	),
+>;
+
 pub fn start_tracking_orthographic_camera(
 	mut commands: Commands,
 	mut events: ResMut<Events<StartTrackingCamera>>,
 	mut images: ResMut<Assets<Image>>,
 	mut smugglers: ResMut<SharedDataSmuggler>,
 	mut recorders: ResMut<ActiveRecorders>,
-	query: Query<(&Camera, &Transform, &OrthographicProjection)>,
+	query: TrackedCameras,
 ) {
 	for event in events.drain() {
-		if let Ok((camera, transform, ortho)) = query.get(event.cam_entity) {
+		if let Ok((camera, transform, ortho, layers, camera_2d)) = query.get(event.cam_entity) {
 			let target_image = ortho.project_to_image();
 			let target_handle = images.add(target_image);
 			let new_id = event.tracking_id;
@@ -121,8 +280,21 @@ pub fn start_tracking_orthographic_camera(
 						target: RenderTarget::Image(target_handle.clone()),
 						..camera.clone()
 					},
+					camera_2d: if event.transparent_background {
+						Camera2d {
+							clear_color: ClearColorConfig::Custom(Color::NONE),
+						}
+					} else {
+						camera_2d.cloned().unwrap_or_default()
+					},
 					..Default::default()
 				})
+				.insert(
+					event
+						.render_layers
+						.or_else(|| layers.copied())
+						.unwrap_or_default(),
+				)
 				.insert(Recorder(event.tracking_id))
 				.insert(Track(event.cam_entity))
 				.id();
@@ -145,6 +317,7 @@ pub fn start_tracking_orthographic_camera(
 					target_handle,
 					target_duration: event.length,
 					frames: Default::default(),
+					filters: Default::default(),
 					tracker: tracker_entity,
 				},
 			);
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+/// Generate a timestamped file name with the given `extension`, for captures that weren't given
+/// a path
+pub fn default_file_name(extension: &str) -> PathBuf {
+	#[cfg(not(target_arch = "wasm32"))]
+	let timestamp = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
+	#[cfg(target_arch = "wasm32")]
+	let timestamp = crate::web_utils::get_now();
+
+	PathBuf::from(format!("{}.{}", timestamp, extension))
+}
+
+/// Persist the encoded bytes of a capture. On desktop this writes a file to `path`, while on
+/// the web target the bytes are offered to the user as a download named after the final
+/// component of `path`.
+///
+/// When no path is given, a timestamped file name with the given `extension` is generated
+pub fn save_bytes(path: Option<PathBuf>, extension: &str, bytes: Vec<u8>) {
+	#[cfg(not(target_arch = "wasm32"))]
+	{
+		let file_name = path.unwrap_or_else(|| default_file_name(extension));
+
+		if let Err(e) = std::fs::write(&file_name, bytes) {
+			log::error!("Failed to write {}: {}", file_name.display(), e);
+		}
+	}
+	#[cfg(target_arch = "wasm32")]
+	{
+		let file_name = path
+			.and_then(|path| {
+				path.file_name()
+					.and_then(|name| name.to_str())
+					.map(|name| PathBuf::from(name))
+			})
+			.unwrap_or_else(|| default_file_name(extension));
+
+		crate::web_utils::download_bytes(file_name, bytes)
+	}
+}
@@ -0,0 +1,272 @@
+//! Draw images and text on top of captured frames before they are encoded
+
+#[cfg(feature = "text-overlay")]
+use ab_glyph::{point, Font, PxScale, ScaleFont};
+use bevy_asset::Assets;
+use bevy_render::texture::Image;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use wgpu::TextureFormat;
+
+use crate::data::{Alignment, FilterContext, FrameFilter, RgbaFrame, Watermark};
+#[cfg(feature = "text-overlay")]
+use crate::data::{OverlayText, TextOverlay};
+use crate::image_utils::{frame_data_to_rgba_image, image_to_rgba_frame, rgba_frame_to_image};
+
+/// A watermark that has been converted to RGBA and scaled, ready to be drawn onto frames
+/// from an async task
+pub struct PreparedWatermark {
+	image: RgbaImage,
+	alignment: Alignment,
+	opacity: f32,
+}
+
+impl PreparedWatermark {
+	/// Look up the image for `watermark`. Returns `None` if the image hasn't loaded yet, or
+	/// uses a texture format that can't be drawn
+	pub fn new(watermark: &Watermark, images: &Assets<Image>) -> Option<Self> {
+		let image = match images.get(&watermark.image) {
+			Some(image) => image,
+			None => {
+				log::warn!("Watermark image is not loaded, capturing without it");
+				return None;
+			}
+		};
+
+		let format = image.texture_descriptor.format;
+		if !matches!(
+			format,
+			TextureFormat::Rgba8UnormSrgb
+				| TextureFormat::Rgba8Unorm
+				| TextureFormat::Bgra8UnormSrgb
+				| TextureFormat::Bgra8Unorm
+		) {
+			log::error!("Unsupported watermark texture format {:?}", format);
+			return None;
+		}
+
+		let (width, height) = (image.size().x as u32, image.size().y as u32);
+		let mut rgba = frame_data_to_rgba_image(width, height, &image.data, format);
+
+		let scale = watermark.scale.max(0.0);
+		if scale != 1.0 {
+			let scaled_width = ((width as f32 * scale).round() as u32).max(1);
+			let scaled_height = ((height as f32 * scale).round() as u32).max(1);
+			rgba = imageops::resize(&rgba, scaled_width, scaled_height, FilterType::Triangle);
+		}
+
+		Some(Self {
+			image: rgba,
+			alignment: watermark.alignment,
+			opacity: watermark.opacity.clamp(0.0, 1.0),
+		})
+	}
+
+	/// Alpha blend the watermark onto `frame`. Any part of the watermark that falls outside of
+	/// the frame is skipped
+	pub fn draw(&self, frame: &mut RgbaImage) {
+		let position = self
+			.alignment
+			.position(frame.dimensions(), self.image.dimensions());
+		draw_layer(frame, &self.image, position, self.opacity);
+	}
+}
+
+impl FrameFilter for PreparedWatermark {
+	fn apply(&self, frame: RgbaFrame, _context: &FilterContext) -> RgbaFrame {
+		let mut image = rgba_frame_to_image(frame);
+		self.draw(&mut image);
+		image_to_rgba_frame(image)
+	}
+}
+
+/// Blend `source` on top of `target`, with the alpha of `source` multiplied by `opacity`
+fn blend_pixel(target: &mut Rgba<u8>, source: &Rgba<u8>, opacity: f32) {
+	let source_alpha = source[3] as f32 / 255.0 * opacity;
+	if source_alpha <= 0.0 {
+		return;
+	}
+
+	let target_alpha = target[3] as f32 / 255.0;
+	let alpha = source_alpha + target_alpha * (1.0 - source_alpha);
+	for channel in 0..3 {
+		let colour = (source[channel] as f32 * source_alpha
+			+ target[channel] as f32 * target_alpha * (1.0 - source_alpha))
+			/ alpha;
+		target[channel] = colour.round() as u8;
+	}
+	target[3] = (alpha * 255.0).round() as u8;
+}
+
+/// Blend every pixel of `layer` onto `frame`, with the top left corner of `layer` placed at
+/// `position`. Any part of the layer that falls outside of the frame is skipped
+fn draw_layer(frame: &mut RgbaImage, layer: &RgbaImage, position: (i64, i64), opacity: f32) {
+	let (left, top) = position;
+	for (x, y, source) in layer.enumerate_pixels() {
+		let (target_x, target_y) = (left + x as i64, top + y as i64);
+		if target_x < 0
+			|| target_y < 0
+			|| target_x >= frame.width() as i64
+			|| target_y >= frame.height() as i64
+		{
+			continue;
+		}
+
+		blend_pixel(
+			frame.get_pixel_mut(target_x as u32, target_y as u32),
+			source,
+			opacity,
+		);
+	}
+}
+
+/// A text overlay for a single capture, which knows when the capture was requested so that
+/// the time each frame was shown can be filled in
+#[cfg(feature = "text-overlay")]
+pub struct PreparedText {
+	overlay: TextOverlay,
+	/// The wall-clock time that the capture was requested, in milliseconds since the epoch
+	captured_at: u64,
+}
+
+#[cfg(feature = "text-overlay")]
+impl PreparedText {
+	/// Prepare an overlay for a capture that is being requested right now
+	pub fn new(overlay: &TextOverlay) -> Self {
+		#[cfg(not(target_arch = "wasm32"))]
+		let captured_at = std::time::UNIX_EPOCH
+			.elapsed()
+			.map(|elapsed| elapsed.as_millis() as u64)
+			.unwrap_or_default();
+		#[cfg(target_arch = "wasm32")]
+		let captured_at = crate::web_utils::get_now() as u64;
+
+		Self {
+			overlay: overlay.clone(),
+			captured_at,
+		}
+	}
+
+	fn line_text(&self, line: &OverlayText, context: &FilterContext) -> String {
+		match line {
+			OverlayText::WallClock => {
+				let shown_at = self
+					.captured_at
+					.saturating_sub(context.ago.as_millis() as u64);
+				let seconds = shown_at / 1000;
+				format!(
+					"{:02}:{:02}:{:02}.{:03} UTC",
+					(seconds / 3600) % 24,
+					(seconds / 60) % 60,
+					seconds % 60,
+					shown_at % 1000
+				)
+			}
+			OverlayText::FrameIndex => format!("Frame {}", context.index),
+			OverlayText::RecorderId => format!("Recorder {}", context.recorder),
+			OverlayText::Text(text) => text.clone(),
+		}
+	}
+
+	/// Draw the text for the frame described by `context` onto `frame`
+	pub fn draw(&self, frame: &mut RgbaImage, context: &FilterContext) {
+		if self.overlay.lines.is_empty() {
+			return;
+		}
+
+		let lines: Vec<String> = self
+			.overlay
+			.lines
+			.iter()
+			.map(|line| self.line_text(line, context))
+			.collect();
+
+		let layer = self.render(&lines);
+		let position = self
+			.overlay
+			.alignment
+			.position(frame.dimensions(), layer.dimensions());
+		draw_layer(frame, &layer, position, 1.0);
+	}
+
+	/// Rasterise `lines` into an image that is just large enough to hold them
+	fn render(&self, lines: &[String]) -> RgbaImage {
+		let font = self
+			.overlay
+			.font
+			.as_scaled(PxScale::from(self.overlay.size));
+		let line_height = font.height() + font.line_gap();
+		let padding = (self.overlay.size / 4.0).ceil();
+
+		let line_width = |line: &str| {
+			let mut previous = None;
+			let mut width = 0.0;
+			for character in line.chars() {
+				let glyph = font.glyph_id(character);
+				if let Some(previous) = previous {
+					width += font.kern(previous, glyph);
+				}
+				width += font.h_advance(glyph);
+				previous = Some(glyph);
+			}
+			width
+		};
+
+		let text_width = lines
+			.iter()
+			.map(|line| line_width(line))
+			.fold(0.0, f32::max);
+		let width = (text_width + padding * 2.0).ceil() as u32;
+		let height = (line_height * lines.len() as f32 + padding * 2.0).ceil() as u32;
+
+		let background = Rgba(self.overlay.background.unwrap_or([0, 0, 0, 0]));
+		let mut layer = RgbaImage::from_pixel(width.max(1), height.max(1), background);
+		let colour = Rgba(self.overlay.colour);
+
+		for (row, line) in lines.iter().enumerate() {
+			let baseline = padding + row as f32 * line_height + font.ascent();
+			let mut x = padding;
+			let mut previous = None;
+			for character in line.chars() {
+				let glyph_id = font.glyph_id(character);
+				if let Some(previous) = previous {
+					x += font.kern(previous, glyph_id);
+				}
+				let glyph = glyph_id.with_scale_and_position(font.scale(), point(x, baseline));
+				x += font.h_advance(glyph_id);
+				previous = Some(glyph_id);
+
+				let outline = match font.outline_glyph(glyph) {
+					Some(outline) => outline,
+					None => continue,
+				};
+				let bounds = outline.px_bounds();
+				outline.draw(|glyph_x, glyph_y, coverage| {
+					let pixel_x = bounds.min.x as i64 + glyph_x as i64;
+					let pixel_y = bounds.min.y as i64 + glyph_y as i64;
+					if pixel_x >= 0
+						&& pixel_y >= 0 && pixel_x < layer.width() as i64
+						&& pixel_y < layer.height() as i64
+					{
+						blend_pixel(
+							layer.get_pixel_mut(pixel_x as u32, pixel_y as u32),
+							&colour,
+							coverage.clamp(0.0, 1.0),
+						);
+					}
+				});
+			}
+		}
+
+		layer
+	}
+}
+
+#[cfg(feature = "text-overlay")]
+impl FrameFilter for PreparedText {
+	fn apply(&self, frame: RgbaFrame, context: &FilterContext) -> RgbaFrame {
+		let mut image = rgba_frame_to_image(frame);
+		self.draw(&mut image, context);
+		image_to_rgba_frame(image)
+	}
+}
@@ -0,0 +1,31 @@
+//! Helpers for writing the RIFF container used by both WebP and AVI files
+
+/// Append a chunk with the given identifier and data to `buffer`. Chunks are padded to an
+/// even size, as required by RIFF
+pub fn push_chunk(buffer: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+	buffer.extend_from_slice(fourcc);
+	buffer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+	buffer.extend_from_slice(data);
+	if data.len() % 2 == 1 {
+		buffer.push(0);
+	}
+}
+
+/// Append a `LIST` chunk of the given type to `buffer`. `data` should be the already
+/// serialised sub-chunks of the list
+pub fn push_list(buffer: &mut Vec<u8>, list_type: &[u8; 4], data: &[u8]) {
+	buffer.extend_from_slice(b"LIST");
+	buffer.extend_from_slice(&(data.len() as u32 + 4).to_le_bytes());
+	buffer.extend_from_slice(list_type);
+	buffer.extend_from_slice(data);
+}
+
+/// Wrap serialised chunks in the top level `RIFF` chunk for a file of the given type
+pub fn riff_file(file_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+	let mut output = Vec::with_capacity(data.len() + 12);
+	output.extend_from_slice(b"RIFF");
+	output.extend_from_slice(&(data.len() as u32 + 4).to_le_bytes());
+	output.extend_from_slice(file_type);
+	output.extend_from_slice(data);
+	output
+}
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::data::TextureFrame;
+
+/// Used when the frame buffer has no measurable duration
+#[cfg(any(feature = "y4m", feature = "video-av1", feature = "pipe"))]
+const FALLBACK_FRAME_RATE: (u64, u64) = (30, 1);
+
+/// The largest denominator used when describing a frame rate as a fraction. This allows
+/// NTSC style rates such as 30000/1001, without the tiny rounding errors in frame times
+/// turning a rate like 60 fps into an enormous fraction
+#[cfg(any(feature = "y4m", feature = "video-av1", feature = "pipe"))]
+const MAX_RATE_DENOMINATOR: u64 = 1001;
+
+/// Find the fraction closest to `value` with a denominator no larger than `max_denominator`,
+/// using its continued fraction expansion
+#[cfg(any(feature = "y4m", feature = "video-av1", feature = "pipe"))]
+fn approximate_fraction(value: f64, max_denominator: u64) -> (u64, u64) {
+	let (mut previous_numerator, mut previous_denominator) = (0u64, 1u64);
+	let (mut numerator, mut denominator) = (1u64, 0u64);
+	let mut remainder = value;
+
+	loop {
+		let whole = remainder.floor() as u64;
+		let next = whole
+			.checked_mul(denominator)
+			.and_then(|term| term.checked_add(previous_denominator))
+			.zip(
+				whole
+					.checked_mul(numerator)
+					.and_then(|term| term.checked_add(previous_numerator)),
+			);
+		let (next_denominator, next_numerator) = match next {
+			Some(next) if next.0 <= max_denominator => next,
+			_ => break,
+		};
+
+		previous_numerator = numerator;
+		previous_denominator = denominator;
+		numerator = next_numerator;
+		denominator = next_denominator;
+
+		let fraction = remainder - remainder.floor();
+		if fraction < 1e-9 {
+			break;
+		}
+		remainder = 1.0 / fraction;
+	}
+
+	(numerator, denominator.max(1))
+}
+
+/// Calculate the average frame rate of a frame buffer, as a reduced `(numerator, denominator)`
+/// fraction of frames per second. Frame times are summed exactly, and the rate is rounded to
+/// the nearest fraction with a denominator of at most 1001.
+///
+/// Frame times vary with the performance of the application, so formats that can only store a
+/// single frame rate use this as an approximation of the buffer's timing
+#[cfg(any(feature = "y4m", feature = "video-av1", feature = "pipe"))]
+pub fn average_frame_rate(frames: &VecDeque<TextureFrame>) -> (u64, u64) {
+	let total_duration = frames
+		.iter()
+		.fold(Duration::ZERO, |total, frame| total + frame.frame_time);
+
+	if frames.is_empty() || total_duration.is_zero() {
+		return FALLBACK_FRAME_RATE;
+	}
+
+	let frames_per_second = frames.len() as f64 / total_duration.as_secs_f64();
+	match approximate_fraction(frames_per_second, MAX_RATE_DENOMINATOR) {
+		(0, _) => FALLBACK_FRAME_RATE,
+		rate => rate,
+	}
+}
+
+/// Convert frame times into whole millisecond delays, for formats that store each frame's
+/// duration in milliseconds. The rounding error of each frame is carried into the next, so
+/// the delays add up to the length of the recording instead of drifting
+#[cfg(any(feature = "apng", feature = "webp", feature = "sprite-sheet"))]
+pub fn millisecond_delays(frame_times: impl IntoIterator<Item = Duration>) -> Vec<u64> {
+	let mut elapsed = Duration::ZERO;
+	let mut written = 0;
+	frame_times
+		.into_iter()
+		.map(|frame_time| {
+			elapsed += frame_time;
+			let end = ((elapsed.as_nanos() + 500_000) / 1_000_000) as u64;
+			let delay = end - written;
+			written = end;
+			delay
+		})
+		.collect()
+}
+
+/// Map a frame buffer with variable frame times onto a constant frame rate, where each output
+/// frame lasts for `frame_duration`. The result contains the index of the buffered frame that
+/// should be shown for each output frame; frames are repeated when they were shown for longer
+/// than one output frame, and skipped when they were shown for less.
+///
+/// Each output frame shows whichever buffered frame was on screen at the midpoint of the
+/// output frame
+pub fn constant_rate_indices(
+	frames: &VecDeque<TextureFrame>,
+	frame_duration: Duration,
+) -> Vec<usize> {
+	if frames.is_empty() || frame_duration.is_zero() {
+		return Vec::new();
+	}
+
+	let total_duration = frames
+		.iter()
+		.fold(Duration::ZERO, |total, frame| total + frame.frame_time);
+	let output_count =
+		((total_duration.as_secs_f64() / frame_duration.as_secs_f64()).round() as usize).max(1);
+
+	let mut indices = Vec::with_capacity(output_count);
+	let mut source = 0;
+	let mut source_end = frames[0].frame_time;
+	for output in 0..output_count {
+		let sample_time = frame_duration.mul_f64(output as f64 + 0.5);
+		while sample_time >= source_end && source + 1 < frames.len() {
+			source += 1;
+			source_end += frames[source].frame_time;
+		}
+		indices.push(source);
+	}
+
+	indices
+}
+
+/// Resample a frame buffer to a constant `frame_rate`, in frames per second, using
+/// [`constant_rate_indices`] to decide which frames to repeat or skip. Every frame in the
+/// output lasts for exactly one frame at the given rate.
+///
+/// When `frame_rate` is `None` the frames are returned unchanged
+pub fn resample(frames: VecDeque<TextureFrame>, frame_rate: Option<u32>) -> VecDeque<TextureFrame> {
+	let frame_rate = match frame_rate {
+		Some(frame_rate) if frame_rate > 0 => frame_rate,
+		_ => return frames,
+	};
+
+	let frame_duration = Duration::from_secs(1) / frame_rate;
+	let indices = constant_rate_indices(&frames, frame_duration);
+	let mut source: Vec<Option<TextureFrame>> = frames.into_iter().map(Some).collect();
+
+	let mut output = VecDeque::with_capacity(indices.len());
+	for (position, &index) in indices.iter().enumerate() {
+		// Indices never decrease, so a frame can be moved into the output the last time
+		// that it is used instead of sharing it with a later output frame
+		let frame = if indices.get(position + 1) == Some(&index) {
+			source[index].clone()
+		} else {
+			source[index].take()
+		};
+
+		if let Some(frame) = frame {
+			output.push_back(TextureFrame {
+				texture: frame.texture,
+				frame_time: frame_duration,
+				region: frame.region,
+				redactions: frame.redactions,
+			});
+		}
+	}
+
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A buffer of `count` frames that each last for `frame_time`. Every frame holds its own
+	/// index as its only byte, so that frames can be told apart after resampling
+	fn frames_at(frame_time: Duration, count: usize) -> VecDeque<TextureFrame> {
+		(0..count)
+			.map(|index| TextureFrame::with_duration(vec![index as u8], frame_time))
+			.collect()
+	}
+
+	fn frame_indices(frames: &VecDeque<TextureFrame>) -> Vec<u8> {
+		frames.iter().map(|frame| frame.texture[0]).collect()
+	}
+
+	#[test]
+	fn constant_rate_indices_repeat_frames_above_the_source_rate() {
+		let frames = frames_at(Duration::from_secs(1) / 30, 30);
+		let indices = constant_rate_indices(&frames, Duration::from_secs(1) / 60);
+		let expected: Vec<usize> = (0..30).flat_map(|index| [index, index]).collect();
+		assert_eq!(indices, expected);
+	}
+
+	#[test]
+	fn constant_rate_indices_skip_frames_below_the_source_rate() {
+		let frames = frames_at(Duration::from_secs(1) / 60, 60);
+		let indices = constant_rate_indices(&frames, Duration::from_secs(1) / 30);
+		let expected: Vec<usize> = (0..30).map(|index| index * 2 + 1).collect();
+		assert_eq!(indices, expected);
+	}
+
+	#[test]
+	fn constant_rate_indices_are_empty_without_frames() {
+		let frame_duration = Duration::from_secs(1) / 30;
+		assert!(constant_rate_indices(&VecDeque::new(), frame_duration).is_empty());
+		assert!(constant_rate_indices(&frames_at(frame_duration, 10), Duration::ZERO).is_empty());
+	}
+
+	#[test]
+	fn resample_gives_every_frame_the_same_duration() {
+		let frames = frames_at(Duration::from_secs(1) / 30, 30);
+		let resampled = resample(frames, Some(60));
+		assert_eq!(resampled.len(), 60);
+		assert!(resampled
+			.iter()
+			.all(|frame| frame.frame_time == Duration::from_secs(1) / 60));
+		assert_eq!(
+			frame_indices(&resampled),
+			(0..30)
+				.flat_map(|index| [index, index])
+				.collect::<Vec<u8>>()
+		);
+	}
+
+	#[test]
+	fn resample_leaves_frames_alone_without_a_rate() {
+		let frames = frames_at(Duration::from_millis(20), 10);
+		assert_eq!(
+			frame_indices(&resample(frames.clone(), None)),
+			frame_indices(&frames)
+		);
+		assert_eq!(
+			frame_indices(&resample(frames.clone(), Some(0))),
+			frame_indices(&frames)
+		);
+		assert!(resample(VecDeque::new(), Some(30)).is_empty());
+	}
+
+	#[cfg(any(feature = "y4m", feature = "video-av1", feature = "pipe"))]
+	#[test]
+	fn average_frame_rate_is_not_skewed_by_truncated_frame_times() {
+		let sixty = frames_at(Duration::from_secs(1) / 60, 300);
+		assert_eq!(average_frame_rate(&sixty), (60, 1));
+
+		let thirty = frames_at(Duration::from_secs(1) / 30, 90);
+		assert_eq!(average_frame_rate(&thirty), (30, 1));
+
+		let ntsc = frames_at(Duration::from_secs_f64(1001.0 / 30000.0), 100);
+		assert_eq!(average_frame_rate(&ntsc), (30000, 1001));
+	}
+
+	#[cfg(any(feature = "y4m", feature = "video-av1", feature = "pipe"))]
+	#[test]
+	fn average_frame_rate_falls_back_without_a_duration() {
+		assert_eq!(average_frame_rate(&VecDeque::new()), FALLBACK_FRAME_RATE);
+		assert_eq!(
+			average_frame_rate(&frames_at(Duration::ZERO, 10)),
+			FALLBACK_FRAME_RATE
+		);
+	}
+
+	#[cfg(any(feature = "apng", feature = "webp", feature = "sprite-sheet"))]
+	#[test]
+	fn millisecond_delays_add_up_to_the_recording_length() {
+		let frames = frames_at(Duration::from_secs(1) / 60, 300);
+		let delays = millisecond_delays(frames.iter().map(|frame| frame.frame_time));
+		assert_eq!(delays.len(), 300);
+		assert_eq!(delays.iter().sum::<u64>(), 5000);
+		assert!(delays.iter().all(|&delay| delay == 16 || delay == 17));
+	}
+}